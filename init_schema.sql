@@ -1,25 +1,108 @@
--- 1. 清理旧表
+-- 1. 清理旧表 (users 与 projects 互相引用，先删除 users 上的外键)
+ALTER TABLE IF EXISTS users DROP CONSTRAINT IF EXISTS users_default_project_fk;
+DROP TABLE IF EXISTS comments;
+DROP TABLE IF EXISTS invite_codes;
+DROP TABLE IF EXISTS oidc_login_states;
+DROP TABLE IF EXISTS user_identities;
+DROP TABLE IF EXISTS personal_access_tokens;
+DROP TABLE IF EXISTS totp_recovery_codes;
+DROP TABLE IF EXISTS password_reset_tokens;
+DROP TABLE IF EXISTS revoked_access_tokens;
+DROP TABLE IF EXISTS refresh_tokens;
+DROP TABLE IF EXISTS issue_relations;
+DROP TABLE IF EXISTS issue_labels;
+DROP TABLE IF EXISTS labels;
+DROP TABLE IF EXISTS issue_assignees;
 DROP TABLE IF EXISTS issues;
+DROP TABLE IF EXISTS workflow_transitions;
+DROP TABLE IF EXISTS workflow_states;
+DROP TABLE IF EXISTS project_transfers;
+DROP TABLE IF EXISTS project_members;
 DROP TABLE IF EXISTS projects;
+DROP TABLE IF EXISTS workspace_members;
+DROP TABLE IF EXISTS workspaces;
 DROP TABLE IF EXISTS users;
 
 -- 2. 创建用户表
 CREATE TABLE users (
     id SERIAL PRIMARY KEY,
     username VARCHAR(50) NOT NULL UNIQUE,
+    -- 仅通过第三方登录创建的用户为空字符串 (没有本地密码)
     password_hash VARCHAR(255) NOT NULL,
+    email VARCHAR(255) UNIQUE,
+    -- 邮箱验证: 为空表示未验证；修改邮箱后重置
+    email_verified_at TIMESTAMP WITH TIME ZONE,
+    -- 最近一次发送验证邮件的时间 (限制重发频率)
+    email_verification_sent_at TIMESTAMP WITH TIME ZONE,
+    -- 角色: user | admin (管理员可访问 /api/admin/*)
+    role VARCHAR(20) NOT NULL DEFAULT 'user' CHECK (role IN ('user', 'admin')),
+    -- 邀请制注册时记录邀请人
+    invited_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    -- 个人资料
+    display_name VARCHAR(100),
+    avatar_url TEXT,
+    -- IANA 时区名，用于按用户本地日期计算截止日期与"今天"视图
+    timezone VARCHAR(64) NOT NULL DEFAULT 'UTC',
+    locale VARCHAR(16) NOT NULL DEFAULT 'zh-CN',
+    -- 每周第一天: 0 = 周日, 1 = 周一 ... 6 = 周六
+    week_start_day SMALLINT NOT NULL DEFAULT 1 CHECK (week_start_day BETWEEN 0 AND 6),
+    -- 默认项目 (外键在 projects 表创建后添加)
+    default_project_id INTEGER,
+    -- 两步验证: 密钥 (Base32)，totp_enabled_at 为空表示尚未确认启用
+    totp_secret VARCHAR(64),
+    totp_enabled_at TIMESTAMP WITH TIME ZONE,
+    -- 最近一次通过验证的时间步，防止验证码重放
+    totp_last_step BIGINT,
+    -- 账户停用时间；申请注销后同时记录计划删除时间，到期由后台任务删除
+    disabled_at TIMESTAMP WITH TIME ZONE,
+    deletion_scheduled_at TIMESTAMP WITH TIME ZONE,
+    -- 管理员停用时间；不为空时用户不能自行恢复账户，只能由管理员重新启用
+    suspended_at TIMESTAMP WITH TIME ZONE,
+    -- 递增后该用户此前签发的所有 Access Token 立即失效
+    token_version INTEGER NOT NULL DEFAULT 0,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
 );
 
--- 3. 创建项目表 (Project)
-CREATE TABLE projects (
+-- 3. 创建工作区表 (Workspace)
+-- 工作区拥有项目；每个用户注册时自动创建一个个人工作区
+CREATE TABLE workspaces (
     id SERIAL PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    -- 个人工作区所属用户 (团队工作区为空)；个人工作区不能邀请成员或删除
+    personal_user_id INTEGER UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    -- 工作区设置: 时区、新项目的默认工作流状态 (第一个为初始状态)、新项目的默认标签
+    timezone VARCHAR(64) NOT NULL DEFAULT 'UTC',
+    default_statuses TEXT[] NOT NULL DEFAULT ARRAY['todo', 'in_progress', 'done', 'canceled'],
+    default_labels TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 工作区成员与角色: owner(所有者) / admin(管理员) / member(成员)
+-- 管理员及以上对工作区内所有项目拥有所有者权限，成员可查看所有项目
+CREATE TABLE workspace_members (
+    workspace_id INTEGER NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
     user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL CHECK (role IN ('owner', 'admin', 'member')),
+    added_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (workspace_id, user_id)
+);
+
+CREATE INDEX idx_workspace_members_user_id ON workspace_members(user_id);
+CREATE UNIQUE INDEX idx_workspace_members_owner ON workspace_members(workspace_id) WHERE role = 'owner';
+
+-- 4. 创建项目表 (Project)
+CREATE TABLE projects (
+    id SERIAL PRIMARY KEY,
+    workspace_id INTEGER NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    -- 项目所有者；注销账户前所有权已交接 (见 access.rs)，这里仅兜底置空，不连带删除项目
+    user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
     name VARCHAR(100) NOT NULL,
     description TEXT,
     
     -- 项目状态: backlog(积压), active(激活), completed(完成), paused(暂停), canceled(取消)
-    status VARCHAR(20) NOT NULL DEFAULT 'active',
+    status VARCHAR(20) NOT NULL DEFAULT 'active' CHECK (status IN ('backlog', 'active', 'completed', 'paused', 'canceled')),
     
     -- 视觉标识 (Linear 风格常用)
     color VARCHAR(7) DEFAULT '#5E6AD2', -- 项目主题色
@@ -28,32 +111,160 @@ CREATE TABLE projects (
     updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
 );
 
--- 4. 创建任务表 (Issue)
+-- 用户的默认项目 (项目删除后置空)
+ALTER TABLE users ADD CONSTRAINT users_default_project_fk
+    FOREIGN KEY (default_project_id) REFERENCES projects(id) ON DELETE SET NULL;
+
+-- 项目成员与角色: owner(所有者) / editor(编辑者) / commenter(评论者) / viewer(查看者)
+-- 所有项目、任务、评论的访问权限都以此表为准
+CREATE TABLE project_members (
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL CHECK (role IN ('owner', 'editor', 'commenter', 'viewer')),
+    added_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (project_id, user_id)
+);
+
+CREATE INDEX idx_project_members_user_id ON project_members(user_id);
+-- 每个项目只有一个所有者
+CREATE UNIQUE INDEX idx_project_members_owner ON project_members(project_id) WHERE role = 'owner';
+
+-- 项目所有权转移请求 (需接收人确认)，同时作为所有权变更历史
+CREATE TABLE project_transfers (
+    id SERIAL PRIMARY KEY,
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    from_user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    to_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- 发起人 (项目所有者或工作区管理员)
+    requested_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'accepted', 'declined', 'canceled', 'expired')),
+    -- 接受后项目迁移前后所在的工作区 (未迁移时两者相同)
+    from_workspace_id INTEGER REFERENCES workspaces(id) ON DELETE SET NULL,
+    to_workspace_id INTEGER REFERENCES workspaces(id) ON DELETE SET NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    responded_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 每个项目同时只能有一个待确认的转移请求
+CREATE UNIQUE INDEX idx_project_transfers_pending ON project_transfers(project_id) WHERE status = 'pending';
+CREATE INDEX idx_project_transfers_to_user_id ON project_transfers(to_user_id);
+
+-- 项目工作流状态 (有序)，新项目按工作区默认状态初始化；任务的 status 即状态名称
+-- 分类: backlog(积压) / unstarted(未开始) / started(进行中) / completed(已完成) / canceled(已取消)
+-- 子任务进度、阻塞判断等按分类而不是状态名称计算
+CREATE TABLE workflow_states (
+    id SERIAL PRIMARY KEY,
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    name VARCHAR(30) NOT NULL,
+    category VARCHAR(20) NOT NULL CHECK (category IN ('backlog', 'unstarted', 'started', 'completed', 'canceled')),
+    -- 排列顺序，最小的为新任务的初始状态
+    position INTEGER NOT NULL,
+    -- 看板中该列的在制品 (WIP) 上限，为空表示不限制
+    wip_limit INTEGER CHECK (wip_limit > 0),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (project_id, name)
+);
+
+-- 允许的状态流转；项目没有任何流转记录时不限制
+CREATE TABLE workflow_transitions (
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    from_state_id INTEGER NOT NULL REFERENCES workflow_states(id) ON DELETE CASCADE,
+    to_state_id INTEGER NOT NULL REFERENCES workflow_states(id) ON DELETE CASCADE,
+    CHECK (from_state_id <> to_state_id),
+    PRIMARY KEY (from_state_id, to_state_id)
+);
+
+CREATE INDEX idx_workflow_transitions_project_id ON workflow_transitions(project_id);
+
+-- 5. 创建任务表 (Issue)
 CREATE TABLE issues (
     id SERIAL PRIMARY KEY,
     project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
-    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE, -- 创建者/负责人
+    -- 创建者 (创建者注销后置空，任务保留在项目中)；负责人见 issue_assignees
+    creator_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    -- 父任务 (同一项目内，不允许成环)
+    parent_id INTEGER REFERENCES issues(id) ON DELETE SET NULL,
     
     title VARCHAR(255) NOT NULL,
     description TEXT, -- 支持 Markdown
     
-    -- 任务状态: backlog, todo, in_progress, done, canceled
-    status VARCHAR(20) NOT NULL DEFAULT 'todo',
+    -- 任务状态: 必须是项目工作流中的状态 (状态改名时同步更新)
+    status VARCHAR(30) NOT NULL,
     
     -- 优先级: 0(无), 1(低), 2(中), 3(高), 4(紧急)
     priority INTEGER NOT NULL DEFAULT 0,
+
+    -- 手动排序键 (看板拖拽)，项目内按字节序排列，见 rank.rs
+    rank TEXT COLLATE "C" NOT NULL,
+    
+    -- 估算工作量 (点数)，用于父任务的加权进度
+    estimate INTEGER CHECK (estimate >= 0),
     
     due_date TIMESTAMP WITH TIME ZONE,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (project_id, status) REFERENCES workflow_states(project_id, name) ON UPDATE CASCADE,
+    -- 可延迟到语句结束检查，便于一次性重新分配整个项目的排序键
+    CONSTRAINT issues_project_rank_key UNIQUE (project_id, rank) DEFERRABLE INITIALLY IMMEDIATE
+);
+
+-- 任务负责人 (可多人，必须能访问任务所属项目)
+CREATE TABLE issue_assignees (
+    issue_id INTEGER NOT NULL REFERENCES issues(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    assigned_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (issue_id, user_id)
+);
+
+-- 标签 (项目内共享，如 "home" / "errands" / "deep work")；新项目按工作区默认标签初始化
+CREATE TABLE labels (
+    id SERIAL PRIMARY KEY,
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    name VARCHAR(50) NOT NULL,
+    color VARCHAR(7) NOT NULL DEFAULT '#8B8D98',
+    description TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
     updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
 );
 
--- 5. 创建索引提高查询效率
+-- 同一项目内标签名不区分大小写唯一
+CREATE UNIQUE INDEX idx_labels_project_name ON labels(project_id, LOWER(name));
+
+CREATE TABLE issue_labels (
+    issue_id INTEGER NOT NULL REFERENCES issues(id) ON DELETE CASCADE,
+    label_id INTEGER NOT NULL REFERENCES labels(id) ON DELETE CASCADE,
+    PRIMARY KEY (issue_id, label_id)
+);
+
+CREATE INDEX idx_issue_labels_label_id ON issue_labels(label_id);
+
+-- 任务关系 (同一项目内): blocks (source 阻塞 target，不允许成环) / duplicates (source 与 target 重复) / relates_to (相关，source < target)
+-- "被阻塞" / "被重复" 通过交换 source 与 target 表示
+CREATE TABLE issue_relations (
+    id SERIAL PRIMARY KEY,
+    source_issue_id INTEGER NOT NULL REFERENCES issues(id) ON DELETE CASCADE,
+    target_issue_id INTEGER NOT NULL REFERENCES issues(id) ON DELETE CASCADE,
+    relation_type VARCHAR(20) NOT NULL CHECK (relation_type IN ('blocks', 'duplicates', 'relates_to')),
+    created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CHECK (source_issue_id <> target_issue_id),
+    UNIQUE (source_issue_id, target_issue_id, relation_type)
+);
+
+CREATE INDEX idx_issue_relations_target ON issue_relations(target_issue_id);
+
+-- 6. 创建索引提高查询效率
 CREATE INDEX idx_projects_user_id ON projects(user_id);
+CREATE INDEX idx_projects_workspace_id ON projects(workspace_id);
 CREATE INDEX idx_issues_project_id ON issues(project_id);
-CREATE INDEX idx_issues_user_id ON issues(user_id);
+CREATE INDEX idx_issues_creator_id ON issues(creator_id);
+CREATE INDEX idx_issues_parent_id ON issues(parent_id);
+CREATE INDEX idx_issue_assignees_user_id ON issue_assignees(user_id);
 
--- 6. 自动更新 updated_at 的触发器函数 (PostgreSQL 特色)
+-- 7. 自动更新 updated_at 的触发器函数 (PostgreSQL 特色)
 CREATE OR REPLACE FUNCTION update_modified_column()
 RETURNS TRIGGER AS $$
 BEGIN
@@ -63,13 +274,116 @@ END;
 $$ language 'plpgsql';
 
 CREATE TRIGGER update_projects_modtime BEFORE UPDATE ON projects FOR EACH ROW EXECUTE PROCEDURE update_modified_column();
-CREATE TRIGGER update_issues_modtime BEFORE UPDATE ON issues FOR EACH ROW EXECUTE PROCEDURE update_modified_column();
+-- 只修改排序键 (重新平衡) 时不更新任务的 updated_at
+CREATE TRIGGER update_issues_modtime BEFORE UPDATE ON issues FOR EACH ROW
+    WHEN (to_jsonb(OLD) - 'rank' IS DISTINCT FROM to_jsonb(NEW) - 'rank')
+    EXECUTE PROCEDURE update_modified_column();
+CREATE TRIGGER update_labels_modtime BEFORE UPDATE ON labels FOR EACH ROW EXECUTE PROCEDURE update_modified_column();
+CREATE TRIGGER update_workspaces_modtime BEFORE UPDATE ON workspaces FOR EACH ROW EXECUTE PROCEDURE update_modified_column();
 
 
 CREATE TABLE refresh_tokens (
     id SERIAL PRIMARY KEY,
     user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
     token TEXT NOT NULL UNIQUE,
+    -- 令牌家族: 同一次登录滚动产生的所有刷新令牌共享一个 family_id
+    family_id UUID NOT NULL,
+    -- 已被用于换取新令牌的时间 (再次出现即视为令牌被盗用)
+    used_at TIMESTAMP WITH TIME ZONE,
+    revoked_at TIMESTAMP WITH TIME ZONE,
+    -- 会话信息: 用于设备管理页面展示
+    user_agent TEXT,
+    ip_address VARCHAR(45),
+    last_used_at TIMESTAMP WITH TIME ZONE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_refresh_tokens_family ON refresh_tokens(user_id, family_id);
+
+-- 密码重置令牌: 只存哈希，单次有效
+CREATE TABLE password_reset_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 两步验证恢复码: 只存哈希，单次有效
+CREATE TABLE totp_recovery_codes (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash VARCHAR(64) NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (user_id, code_hash)
+);
+
+-- 个人访问令牌: 只存哈希，token_prefix 用于在列表中辨认
+-- scopes: projects:read / projects:write / projects:admin / issues:read / issues:write
+CREATE TABLE personal_access_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    token_prefix VARCHAR(20) NOT NULL,
+    scopes TEXT[] NOT NULL DEFAULT '{}',
+    expires_at TIMESTAMP WITH TIME ZONE,
+    last_used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_personal_access_tokens_user_id ON personal_access_tokens(user_id);
+
+-- 邀请码 (REGISTRATION_MODE=invite 时注册必须使用)，只存哈希
+CREATE TABLE invite_codes (
+    id SERIAL PRIMARY KEY,
+    -- 创建者 (管理员或普通用户)，用户删除后保留邀请码记录
+    created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    code_hash VARCHAR(64) NOT NULL UNIQUE,
+    code_prefix VARCHAR(20) NOT NULL,
+    max_uses INTEGER NOT NULL DEFAULT 1 CHECK (max_uses > 0),
+    use_count INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    revoked_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_invite_codes_created_by ON invite_codes(created_by);
+
+-- 第三方身份 (OIDC): 以 issuer + subject 唯一标识
+CREATE TABLE user_identities (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    issuer TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    email VARCHAR(255),
+    last_login_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (issuer, subject)
+);
+
+CREATE INDEX idx_user_identities_user_id ON user_identities(user_id);
+
+-- OIDC 授权请求: state (哈希) 单次有效，保存 nonce 与 PKCE verifier
+CREATE TABLE oidc_login_states (
+    state VARCHAR(64) PRIMARY KEY,
+    nonce TEXT NOT NULL,
+    code_verifier TEXT NOT NULL,
+    -- 非空表示已登录用户发起的关联流程
+    link_user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+    -- 发起登录时填写的邀请码 (哈希)，首次登录需要创建账户时核销
+    invite_code_hash VARCHAR(64),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Access Token 黑名单 (按 jti)，过期后可清理
+CREATE TABLE revoked_access_tokens (
+    jti UUID PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
     expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
 );
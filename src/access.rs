@@ -0,0 +1,350 @@
+// src/access.rs
+// 工作区与项目权限：所有工作区/项目/任务/评论接口都通过这里判断当前用户的角色
+// 项目角色 = max(项目成员角色, 工作区角色隐含的项目角色)
+// 非成员一律返回 404，不暴露资源是否存在
+use crate::auth::AuthUser;
+use crate::AppError;
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+
+/// 当前用户可见的项目 ID 子查询 (用户 ID 必须绑定为 $1)
+pub const VISIBLE_PROJECTS_SQL: &str = r#"
+    SELECT vm.project_id FROM project_members vm WHERE vm.user_id = $1
+    UNION
+    SELECT vp.id FROM projects vp
+    JOIN workspace_members vw ON vw.workspace_id = vp.workspace_id
+    WHERE vw.user_id = $1"#;
+
+/// 能访问项目的用户 ID 子查询 (项目 ID 必须绑定为 $1)，与 project_role_of 的规则一致
+pub const PROJECT_USERS_SQL: &str = r#"
+    SELECT um.user_id FROM project_members um WHERE um.project_id = $1
+    UNION
+    SELECT uw.user_id FROM workspace_members uw
+    JOIN projects up ON up.workspace_id = uw.workspace_id
+    WHERE up.id = $1"#;
+
+/// 工作区成员角色，按权限从低到高排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum WorkspaceRole {
+    /// 可查看工作区内所有项目，可创建项目
+    Member,
+    /// 管理成员与设置，对工作区内所有项目拥有所有者权限
+    Admin,
+    /// 工作区创建者，可删除工作区，每个工作区只有一个
+    Owner,
+}
+
+impl WorkspaceRole {
+    fn label(&self) -> &'static str {
+        match self {
+            WorkspaceRole::Member => "成员",
+            WorkspaceRole::Admin => "管理员",
+            WorkspaceRole::Owner => "所有者",
+        }
+    }
+
+    /// 工作区角色对其下所有项目隐含的项目角色
+    fn project_role(&self) -> ProjectRole {
+        match self {
+            WorkspaceRole::Member => ProjectRole::Viewer,
+            WorkspaceRole::Admin | WorkspaceRole::Owner => ProjectRole::Owner,
+        }
+    }
+}
+
+/// 项目成员角色，按权限从低到高排列 (比较大小即可判断权限)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum ProjectRole {
+    /// 只读
+    Viewer,
+    /// 只读 + 发表评论
+    Commenter,
+    /// 创建/修改/删除任务，修改项目信息
+    Editor,
+    /// 管理成员、删除项目，每个项目只有一个
+    Owner,
+}
+
+impl ProjectRole {
+    fn label(&self) -> &'static str {
+        match self {
+            ProjectRole::Viewer => "查看者",
+            ProjectRole::Commenter => "评论者",
+            ProjectRole::Editor => "编辑者",
+            ProjectRole::Owner => "所有者",
+        }
+    }
+}
+
+fn ensure_role(role: ProjectRole, min_role: ProjectRole) -> Result<ProjectRole, AppError> {
+    if role >= min_role {
+        Ok(role)
+    } else {
+        Err(AppError::Forbidden(format!("需要项目{}及以上权限", min_role.label())))
+    }
+}
+
+fn effective_role(project_role: Option<ProjectRole>, workspace_role: Option<WorkspaceRole>) -> Option<ProjectRole> {
+    project_role.max(workspace_role.map(|r| r.project_role()))
+}
+
+/// 要求当前用户在工作区中的角色不低于 min_role，返回实际角色
+pub async fn authorize_workspace(
+    db: &PgPool,
+    user: &AuthUser,
+    workspace_id: i32,
+    min_role: WorkspaceRole,
+) -> Result<WorkspaceRole, AppError> {
+    let role: Option<(WorkspaceRole,)> =
+        sqlx::query_as("SELECT role FROM workspace_members WHERE workspace_id = $1 AND user_id = $2")
+            .bind(workspace_id)
+            .bind(user.id)
+            .fetch_optional(db)
+            .await?;
+
+    match role {
+        Some((role,)) if role >= min_role => Ok(role),
+        Some(_) => Err(AppError::Forbidden(format!("需要工作区{}及以上权限", min_role.label()))),
+        None => Err(AppError::NotFound("工作区不存在或无权访问".into())),
+    }
+}
+
+/// 查询任意用户在项目中的实际角色 (无权访问时为 None)
+pub async fn project_role_of(db: &PgPool, user_id: i32, project_id: i32) -> Result<Option<ProjectRole>, AppError> {
+    let row: Option<(Option<ProjectRole>, Option<WorkspaceRole>)> = sqlx::query_as(
+        r#"SELECT pm.role, wm.role
+           FROM projects p
+           LEFT JOIN project_members pm ON pm.project_id = p.id AND pm.user_id = $2
+           LEFT JOIN workspace_members wm ON wm.workspace_id = p.workspace_id AND wm.user_id = $2
+           WHERE p.id = $1"#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.and_then(|(pm, wm)| effective_role(pm, wm)))
+}
+
+/// 要求当前用户在项目中的角色不低于 min_role，返回实际角色
+pub async fn authorize_project(
+    db: &PgPool,
+    user: &AuthUser,
+    project_id: i32,
+    min_role: ProjectRole,
+) -> Result<ProjectRole, AppError> {
+    match project_role_of(db, user.id, project_id).await? {
+        Some(role) => ensure_role(role, min_role),
+        None => Err(AppError::NotFound("项目不存在或无权访问".into())),
+    }
+}
+
+/// 按任务所属项目判断权限，返回 (项目 ID, 角色)
+pub async fn authorize_issue(
+    db: &PgPool,
+    user: &AuthUser,
+    issue_id: i32,
+    min_role: ProjectRole,
+) -> Result<(i32, ProjectRole), AppError> {
+    let row: Option<(i32, Option<ProjectRole>, Option<WorkspaceRole>)> = sqlx::query_as(
+        r#"SELECT i.project_id, pm.role, wm.role
+           FROM issues i
+           JOIN projects p ON p.id = i.project_id
+           LEFT JOIN project_members pm ON pm.project_id = i.project_id AND pm.user_id = $2
+           LEFT JOIN workspace_members wm ON wm.workspace_id = p.workspace_id AND wm.user_id = $2
+           WHERE i.id = $1"#,
+    )
+    .bind(issue_id)
+    .bind(user.id)
+    .fetch_optional(db)
+    .await?;
+
+    match row.and_then(|(project_id, pm, wm)| Some((project_id, effective_role(pm, wm)?))) {
+        Some((project_id, role)) => Ok((project_id, ensure_role(role, min_role)?)),
+        None => Err(AppError::NotFound("任务不存在或无权访问".into())),
+    }
+}
+
+/// 按标签所属项目判断权限，返回 (项目 ID, 角色)
+pub async fn authorize_label(
+    db: &PgPool,
+    user: &AuthUser,
+    label_id: i32,
+    min_role: ProjectRole,
+) -> Result<(i32, ProjectRole), AppError> {
+    let not_found = || AppError::NotFound("标签不存在或无权访问".into());
+    let (project_id,): (i32,) = sqlx::query_as("SELECT project_id FROM labels WHERE id = $1")
+        .bind(label_id)
+        .fetch_optional(db)
+        .await?
+        .ok_or_else(not_found)?;
+
+    match project_role_of(db, user.id, project_id).await? {
+        Some(role) => Ok((project_id, ensure_role(role, min_role)?)),
+        None => Err(not_found()),
+    }
+}
+
+/// 注销账户前交接所有权，必须与删除用户在同一事务中执行：
+/// - 用户拥有的团队工作区交给最早加入的管理员 (没有则最早加入的成员)，没有其他成员时随账户删除
+/// - 用户拥有的其他项目交给所在工作区的所有者，并记录一条已接受的转移
+/// - 会随账户一起删除的项目 (个人工作区或无人接手的工作区中) 若还有其他成员，则拒绝注销
+pub async fn release_account_ownership(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: i32,
+) -> Result<(), AppError> {
+    let owned_workspaces: Vec<i32> = sqlx::query_scalar(
+        r#"SELECT w.id FROM workspaces w
+           JOIN workspace_members wm ON wm.workspace_id = w.id
+           WHERE wm.user_id = $1 AND wm.role = 'owner' AND w.personal_user_id IS NULL
+           ORDER BY w.id
+           FOR UPDATE OF w"#,
+    )
+    .bind(user_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let mut abandoned_workspaces = Vec::new();
+    for workspace_id in owned_workspaces {
+        let successor: Option<i32> = sqlx::query_scalar(
+            r#"SELECT user_id FROM workspace_members WHERE workspace_id = $1 AND user_id <> $2
+               ORDER BY (role = 'admin') DESC, created_at, user_id LIMIT 1"#,
+        )
+        .bind(workspace_id)
+        .bind(user_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+        let Some(successor_id) = successor else {
+            abandoned_workspaces.push(workspace_id);
+            continue;
+        };
+        // 先移除原所有者，腾出"每个工作区一个所有者"的唯一索引
+        sqlx::query("DELETE FROM workspace_members WHERE workspace_id = $1 AND user_id = $2")
+            .bind(workspace_id)
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await?;
+        sqlx::query("UPDATE workspace_members SET role = 'owner' WHERE workspace_id = $1 AND user_id = $2")
+            .bind(workspace_id)
+            .bind(successor_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    let owned_projects: Vec<(i32, String, i32, bool)> = sqlx::query_as(
+        r#"SELECT p.id, p.name, p.workspace_id,
+                  (w.personal_user_id IS NOT DISTINCT FROM $1 OR w.id = ANY($2)) AS removed
+           FROM project_members pm
+           JOIN projects p ON p.id = pm.project_id
+           JOIN workspaces w ON w.id = p.workspace_id
+           WHERE pm.user_id = $1 AND pm.role = 'owner'
+           ORDER BY p.id
+           FOR UPDATE OF p"#,
+    )
+    .bind(user_id)
+    .bind(&abandoned_workspaces)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let mut shared = Vec::new();
+    for (project_id, name, workspace_id, removed) in owned_projects {
+        let successor: Option<i32> = if removed {
+            None
+        } else {
+            sqlx::query_scalar("SELECT user_id FROM workspace_members WHERE workspace_id = $1 AND role = 'owner'")
+                .bind(workspace_id)
+                .fetch_optional(&mut **tx)
+                .await?
+                .filter(|&id| id != user_id)
+        };
+        let Some(successor_id) = successor else {
+            let has_members: bool =
+                sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM project_members WHERE project_id = $1 AND user_id <> $2)")
+                    .bind(project_id)
+                    .bind(user_id)
+                    .fetch_one(&mut **tx)
+                    .await?;
+            if has_members {
+                shared.push(name);
+            }
+            continue;
+        };
+
+        sqlx::query("DELETE FROM project_members WHERE project_id = $1 AND user_id = $2")
+            .bind(project_id)
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await?;
+        sqlx::query(
+            r#"INSERT INTO project_members (project_id, user_id, role) VALUES ($1, $2, 'owner')
+               ON CONFLICT (project_id, user_id) DO UPDATE SET role = 'owner'"#,
+        )
+        .bind(project_id)
+        .bind(successor_id)
+        .execute(&mut **tx)
+        .await?;
+        sqlx::query("UPDATE projects SET user_id = $1, updated_at = NOW() WHERE id = $2")
+            .bind(successor_id)
+            .bind(project_id)
+            .execute(&mut **tx)
+            .await?;
+        sqlx::query(
+            "UPDATE project_transfers SET status = 'canceled', responded_at = NOW() WHERE project_id = $1 AND status = 'pending'",
+        )
+        .bind(project_id)
+        .execute(&mut **tx)
+        .await?;
+        sqlx::query(
+            r#"INSERT INTO project_transfers
+                   (project_id, from_user_id, to_user_id, status, from_workspace_id, to_workspace_id, expires_at, responded_at)
+               VALUES ($1, $2, $3, 'accepted', $4, $4, NOW(), NOW())"#,
+        )
+        .bind(project_id)
+        .bind(user_id)
+        .bind(successor_id)
+        .bind(workspace_id)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    if !shared.is_empty() {
+        return Err(AppError::Conflict(format!(
+            "以下项目仍有其他成员，会随账户一起删除，请先转移所有权或删除后再注销: {}",
+            shared.join("、")
+        )));
+    }
+
+    sqlx::query("DELETE FROM workspaces WHERE id = ANY($1)")
+        .bind(&abandoned_workspaces)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// 列表接口的工作区范围：请求头 X-Workspace-Id，未指定时包含所有可访问的工作区
+pub struct WorkspaceScope(pub Option<i32>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for WorkspaceScope
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.headers.get("x-workspace-id") {
+            None => Ok(WorkspaceScope(None)),
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .map(|id| WorkspaceScope(Some(id)))
+                .ok_or_else(|| AppError::BadRequest("X-Workspace-Id 格式错误".into())),
+        }
+    }
+}
@@ -3,35 +3,42 @@ use argon2::{
     password_hash::{
         rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString
     },
-    Argon2,
+    Algorithm, Argon2, Params, Version,
 };
 use serde::{Deserialize, Serialize};
-use jsonwebtoken::{encode, decode, Header, Algorithm, Validation, EncodingKey, DecodingKey};
 use chrono::{Utc, Duration};
 use axum::{
     async_trait,
-    extract::FromRequestParts,
-    http::request::Parts,
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::{header, request::Parts, HeaderMap},
     RequestPartsExt,
 };
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
 use axum_extra::{
     headers::{authorization::Bearer, Authorization},
     TypedHeader,
 };
-use crate::AppError; 
+use argon2::password_hash::rand_core::RngCore;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+use crate::config::{EmailVerificationPolicy, IpNetwork};
+use crate::keys::KeyManager;
+use crate::{AppError, AppState};
 use uuid::Uuid;
 
 // --- 1. 密码处理 (Argon2) ---
 
-pub fn hash_password(password: &str) -> Result<String, String> {
+pub fn hash_password(password: &str, params: &Params) -> Result<String, String> {
     let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone());
     let password_hash = argon2.hash_password(password.as_bytes(), &salt)
         .map_err(|e| e.to_string())?
         .to_string();
     Ok(password_hash)
 }
 
+/// 校验时使用哈希字符串中记录的算法、版本与参数，因此旧参数生成的哈希依然有效
 pub fn verify_password(password: &str, password_hash: &str) -> bool {
     let parsed_hash = match PasswordHash::new(password_hash) {
         Ok(h) => h,
@@ -40,19 +47,75 @@ pub fn verify_password(password: &str, password_hash: &str) -> bool {
     Argon2::default().verify_password(password.as_bytes(), &parsed_hash).is_ok()
 }
 
+/// 哈希的算法/版本与当前不同，或任一成本参数低于当前配置时需要重新哈希
+pub fn password_needs_rehash(password_hash: &str, params: &Params) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(password_hash) else {
+        return false;
+    };
+    if Algorithm::try_from(parsed_hash.algorithm) != Ok(Algorithm::Argon2id)
+        || parsed_hash.version != Some(Version::V0x13 as u32)
+    {
+        return true;
+    }
+    match Params::try_from(&parsed_hash) {
+        Ok(current) => {
+            current.m_cost() < params.m_cost()
+                || current.t_cost() < params.t_cost()
+                || current.p_cost() < params.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+/// 用户不存在 (或没有设置密码) 时用于校验的占位哈希
+/// 保证这类请求与密码错误的耗时一致，避免通过响应时间枚举用户名
+pub fn dummy_password_hash(params: &Params) -> &'static str {
+    static DUMMY: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    DUMMY.get_or_init(|| {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        hash_password(&URL_SAFE_NO_PAD.encode(bytes), params).expect("failed to hash dummy password")
+    })
+}
+
 // --- 2. JWT (Access Token) 处理 ---
 
+/// 用户角色：admin 可以访问 /api/admin/* 管理接口
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: i32,         // 用户 ID
     pub username: String, 
+    #[serde(default)]
+    pub role: Role,       // 旧版 Token 中没有此字段，视为普通用户
     pub exp: usize,       // 过期时间
+    pub iat: usize,       // 签发时间
+    pub jti: Uuid,        // Token 唯一 ID (用于黑名单)
+    pub ver: i32,         // 签发时用户的 token_version
+    #[serde(default)]
+    pub sid: Option<Uuid>, // 会话 ID (即刷新令牌家族 ID)
 }
 
 /// 生成短效的 Access Token (用于 API 请求)
 /// 建议有效期：15 分钟
-pub fn create_jwt(user_id: i32, username: &str) -> Result<String, String> {
-    let expiration = Utc::now()
+pub fn create_jwt(
+    keys: &KeyManager,
+    user_id: i32,
+    username: &str,
+    role: Role,
+    token_version: i32,
+    session_id: Uuid,
+) -> Result<String, String> {
+    let now = Utc::now();
+    let expiration = now
         .checked_add_signed(Duration::minutes(15)) 
         .expect("valid timestamp")
         .timestamp();
@@ -60,66 +123,429 @@ pub fn create_jwt(user_id: i32, username: &str) -> Result<String, String> {
     let claims = Claims {
         sub: user_id,
         username: username.to_owned(),
+        role,
         exp: expiration as usize,
+        iat: now.timestamp() as usize,
+        jti: Uuid::new_v4(),
+        ver: token_version,
+        sid: Some(session_id),
     };
 
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
-    .map_err(|e| e.to_string())
+    keys.sign(&claims)
+}
+
+// --- 2.1 两步验证中间态 Token ---
+// 密码验证通过但尚未提交 TOTP 验证码时签发，只能用于 /api/login/2fa
+// 没有 username 字段，无法被当作 Access Token 解析
+
+pub const MFA_PENDING: &str = "mfa_pending";
+
+/// 中间态 Token 有效期 (分钟)
+pub const MFA_TOKEN_TTL_MINUTES: i64 = 5;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaClaims {
+    pub sub: i32,
+    pub exp: usize,
+    pub iat: usize,
+    pub jti: Uuid,
+    pub ver: i32,
+    pub purpose: String, // 固定为 "mfa_pending"
+    /// 由撤销注销申请发起：第二因素验证通过后才恢复账户
+    #[serde(default)]
+    pub restore: bool,
+}
+
+pub fn create_mfa_token(keys: &KeyManager, user_id: i32, token_version: i32, restore: bool) -> Result<String, String> {
+    let now = Utc::now();
+    let claims = MfaClaims {
+        sub: user_id,
+        exp: (now + Duration::minutes(MFA_TOKEN_TTL_MINUTES)).timestamp() as usize,
+        iat: now.timestamp() as usize,
+        jti: Uuid::new_v4(),
+        ver: token_version,
+        purpose: MFA_PENDING.to_string(),
+        restore,
+    };
+    keys.sign(&claims)
+}
+
+pub fn verify_mfa_token(keys: &KeyManager, token: &str) -> Result<MfaClaims, AppError> {
+    let claims = keys
+        .verify::<MfaClaims>(token)
+        .map_err(|_| AppError::Auth("验证已超时，请重新登录".into()))?
+        .claims;
+    if claims.purpose != MFA_PENDING {
+        return Err(AppError::Auth("验证已超时，请重新登录".into()));
+    }
+    Ok(claims)
+}
+
+// --- 2.2 邮箱验证 Token ---
+// 放在验证邮件的链接中；绑定邮箱地址，用户修改邮箱后旧链接自动失效
+
+pub const EMAIL_VERIFY: &str = "email_verify";
+
+/// 验证链接有效期 (小时)
+pub const EMAIL_VERIFICATION_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailVerificationClaims {
+    pub sub: i32,
+    pub email: String,
+    pub exp: usize,
+    pub iat: usize,
+    pub purpose: String, // 固定为 "email_verify"
+}
+
+pub fn create_email_verification_token(keys: &KeyManager, user_id: i32, email: &str) -> Result<String, String> {
+    let now = Utc::now();
+    let claims = EmailVerificationClaims {
+        sub: user_id,
+        email: email.to_string(),
+        exp: (now + Duration::hours(EMAIL_VERIFICATION_TTL_HOURS)).timestamp() as usize,
+        iat: now.timestamp() as usize,
+        purpose: EMAIL_VERIFY.to_string(),
+    };
+    keys.sign(&claims)
+}
+
+pub fn verify_email_verification_token(keys: &KeyManager, token: &str) -> Result<EmailVerificationClaims, AppError> {
+    let claims = keys
+        .verify::<EmailVerificationClaims>(token)
+        .map_err(|_| AppError::BadRequest("验证链接无效或已过期".into()))?
+        .claims;
+    if claims.purpose != EMAIL_VERIFY {
+        return Err(AppError::BadRequest("验证链接无效或已过期".into()));
+    }
+    Ok(claims)
 }
 
 // --- 3. Refresh Token 处理 ---
 
+/// 刷新令牌有效期 (天)，每次滚动刷新都会重新计算
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 7;
+
+/// 已使用过的刷新令牌再次出现时返回的错误原因
+/// 前端拦截器据此强制用户重新登录
+pub const REFRESH_TOKEN_REUSED: &str = "检测到登录凭证被重复使用，请重新登录";
+
 /// 生成唯一的随机字符串作为刷新令牌
 pub fn generate_refresh_token() -> String {
     Uuid::new_v4().to_string()
 }
 
+// --- 3.1 一次性令牌 (密码重置等) ---
+
+/// 生成 256 位随机令牌 (URL 安全)，明文只发给用户
+pub fn generate_secure_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// 数据库中只保存令牌的 SHA-256 哈希
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+// --- 3.2 个人访问令牌 (Personal Access Token) ---
+// 供脚本和命令行工具使用，长期有效，只能访问授权范围 (scope) 内的接口
+
+/// 个人访问令牌前缀，AuthUser 据此区分 PAT 与 JWT
+pub const PAT_PREFIX: &str = "mlp_";
+
+pub fn generate_personal_access_token() -> String {
+    format!("{}{}", PAT_PREFIX, generate_secure_token())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "projects:read")]
+    ProjectsRead,
+    #[serde(rename = "projects:write")]
+    ProjectsWrite,
+    /// 删除项目等高危操作
+    #[serde(rename = "projects:admin")]
+    ProjectsAdmin,
+    #[serde(rename = "issues:read")]
+    IssuesRead,
+    /// 创建/修改/删除任务与评论
+    #[serde(rename = "issues:write")]
+    IssuesWrite,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ProjectsRead => "projects:read",
+            Scope::ProjectsWrite => "projects:write",
+            Scope::ProjectsAdmin => "projects:admin",
+            Scope::IssuesRead => "issues:read",
+            Scope::IssuesWrite => "issues:write",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Scope> {
+        [
+            Scope::ProjectsRead,
+            Scope::ProjectsWrite,
+            Scope::ProjectsAdmin,
+            Scope::IssuesRead,
+            Scope::IssuesWrite,
+        ]
+        .into_iter()
+        .find(|scope| scope.as_str() == s)
+    }
+
+    /// 高级别权限包含同一资源的低级别权限 (admin ⊃ write ⊃ read)
+    fn grants(&self, required: Scope) -> bool {
+        use Scope::*;
+        *self == required
+            || matches!(
+                (self, required),
+                (ProjectsAdmin, ProjectsWrite | ProjectsRead)
+                    | (ProjectsWrite, ProjectsRead)
+                    | (IssuesWrite, IssuesRead)
+            )
+    }
+}
+
 // --- 4. 核心：认证提取器 (AuthUser Extractor) ---
 // 用于在 Handler 中通过 (user: AuthUser) 自动获取当前登录用户
+// 同时接受登录签发的 JWT 和个人访问令牌
 
 pub struct AuthUser {
     pub id: i32,
     #[allow(dead_code)] 
     pub username: String,
+    /// 个人访问令牌始终视为普通用户
+    pub role: Role,
+    pub credential: Credential,
+}
+
+pub enum Credential {
+    /// 登录签发的 Access Token，拥有账户的全部权限
+    Jwt {
+        /// 签发该 Token 的会话，旧版 Token 中没有此字段
+        session_id: Option<Uuid>,
+        /// jti 与过期时间，退出登录时用于加入黑名单
+        jti: Uuid,
+        exp: usize,
+    },
+    /// 个人访问令牌，只能访问 scopes 允许的接口
+    PersonalToken { scopes: Vec<Scope> },
+}
+
+impl AuthUser {
+    pub fn session_id(&self) -> Option<Uuid> {
+        match &self.credential {
+            Credential::Jwt { session_id, .. } => *session_id,
+            Credential::PersonalToken { .. } => None,
+        }
+    }
+
+    /// 要求令牌具备指定权限 (JWT 视为拥有全部权限)
+    pub fn require_scope(&self, scope: Scope) -> Result<(), AppError> {
+        match &self.credential {
+            Credential::Jwt { .. } => Ok(()),
+            Credential::PersonalToken { scopes } if scopes.iter().any(|s| s.grants(scope)) => Ok(()),
+            Credential::PersonalToken { .. } => {
+                Err(AppError::Forbidden(format!("访问令牌缺少权限: {}", scope.as_str())))
+            }
+        }
+    }
+
+    /// 配置要求验证邮箱后才能修改数据时，检查当前用户的邮箱是否已验证
+    pub async fn require_verified_email(&self, state: &AppState) -> Result<(), AppError> {
+        if state.config.email_verification < EmailVerificationPolicy::RequiredForWrites {
+            return Ok(());
+        }
+        let verified: Option<(bool,)> =
+            sqlx::query_as("SELECT email_verified_at IS NOT NULL FROM users WHERE id = $1")
+                .bind(self.id)
+                .fetch_optional(&state.db)
+                .await?;
+        match verified {
+            Some((true,)) => Ok(()),
+            _ => Err(AppError::Forbidden("请先验证邮箱".into())),
+        }
+    }
+
+    /// 账户相关接口 (密码、会话、令牌管理等) 只允许登录会话访问
+    pub fn require_session(&self) -> Result<(), AppError> {
+        match &self.credential {
+            Credential::Jwt { .. } => Ok(()),
+            Credential::PersonalToken { .. } => Err(AppError::Forbidden("该接口不支持个人访问令牌".into())),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PersonalTokenRow {
+    id: i32,
+    user_id: i32,
+    username: String,
+    scopes: Vec<String>,
+}
+
+/// 校验个人访问令牌，并记录最近使用时间 (每分钟最多写一次)
+async fn authenticate_personal_token(state: &AppState, token: &str) -> Result<AuthUser, AppError> {
+    let row = sqlx::query_as::<_, PersonalTokenRow>(
+        r#"SELECT p.id, p.user_id, u.username, p.scopes
+           FROM personal_access_tokens p
+           JOIN users u ON p.user_id = u.id
+           WHERE p.token_hash = $1 AND (p.expires_at IS NULL OR p.expires_at > NOW())
+             AND u.disabled_at IS NULL"#,
+    )
+    .bind(hash_token(token))
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| AppError::Auth("访问令牌无效或已过期".into()))?;
+
+    sqlx::query(
+        r#"UPDATE personal_access_tokens SET last_used_at = NOW()
+           WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')"#,
+    )
+    .bind(row.id)
+    .execute(&state.db)
+    .await?;
+
+    Ok(AuthUser {
+        id: row.user_id,
+        username: row.username,
+        role: Role::User,
+        credential: Credential::PersonalToken {
+            scopes: row.scopes.iter().filter_map(|s| Scope::parse(s)).collect(),
+        },
+    })
 }
 
 #[async_trait]
 impl<S> FromRequestParts<S> for AuthUser
 where
+    AppState: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = AppError;
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+
         // 1. 从 HTTP Header 提取 Bearer Token
         let TypedHeader(Authorization(bearer)) = parts
             .extract::<TypedHeader<Authorization<Bearer>>>()
             .await
             .map_err(|_| AppError::Auth("Token 缺失或格式错误".into()))?;
 
+        if bearer.token().starts_with(PAT_PREFIX) {
+            return authenticate_personal_token(&state, bearer.token()).await;
+        }
+
         // 2. 验证 Token 有效性
-        let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "secret".into());
-        let token_data = decode::<Claims>(
-            bearer.token(),
-            &DecodingKey::from_secret(secret.as_bytes()),
-            &Validation::new(Algorithm::HS256),
-        )
-        .map_err(|e| {
+        let token_data = state.keys.verify::<Claims>(bearer.token()).map_err(|e| {
             // 如果 Token 过期，jsonwebtoken 会返回特定错误，前端拦截器会捕获并处理
             tracing::warn!("JWT 验证失败: {}", e);
             AppError::Auth("Token 已过期或无效".into())
         })?;
 
-        // 3. 验证通过，构建 AuthUser
+        // 3. 检查 Token 是否已被吊销 (修改密码、退出所有设备、退出登录)
+        let claims = &token_data.claims;
+        state.revocation.check(&state.db, claims.sub, claims.ver, claims.jti).await?;
+
+        // 4. 验证通过，构建 AuthUser
         Ok(AuthUser {
             id: token_data.claims.sub,
             username: token_data.claims.username,
+            role: token_data.claims.role,
+            credential: Credential::Jwt {
+                session_id: token_data.claims.sid,
+                jti: token_data.claims.jti,
+                exp: token_data.claims.exp,
+            },
         })
     }
-}
\ No newline at end of file
+}
+
+// --- 4.1 管理员提取器 ---
+// 用于 /api/admin/* 接口：必须是管理员的登录会话 (不接受个人访问令牌)
+
+pub struct AdminUser(pub AuthUser);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminUser
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        match (&user.credential, user.role) {
+            (Credential::Jwt { .. }, Role::Admin) => Ok(AdminUser(user)),
+            _ => Err(AppError::Forbidden("需要管理员权限".into())),
+        }
+    }
+}
+
+// --- 5. 客户端信息提取器 ---
+// 记录会话来源 (设备管理页面展示)，提取失败时字段为空而不是拒绝请求
+
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.chars().take(512).collect());
+
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let ip_address = peer
+            .map(|peer| client_ip(peer, &parts.headers, &state.config.trusted_proxies))
+            .map(|ip| ip.to_string());
+
+        Ok(ClientInfo { user_agent, ip_address })
+    }
+}
+
+/// 客户端真实地址：TCP 对端不是可信代理时直接使用对端地址 (X-Forwarded-For 可被任意伪造)；
+/// 否则从 X-Forwarded-For 最右侧向左跳过可信代理，取第一个不可信的地址
+fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted: &[IpNetwork]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|net| net.contains(ip));
+    if !is_trusted(peer) {
+        return peer;
+    }
+    let hops: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .collect();
+    let mut client = peer;
+    for hop in hops.into_iter().rev() {
+        // 无法解析的条目及其左侧内容都不可信
+        let Ok(ip) = hop.trim().parse::<IpAddr>() else { break };
+        client = ip;
+        if !is_trusted(ip) {
+            break;
+        }
+    }
+    client
+}
@@ -0,0 +1,92 @@
+// src/calendar.rs
+// 按用户时区计算"今天""本周"等本地日期范围，换算成 UTC 时间后用于查询截止日期
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::models::DueFilter;
+
+/// 本地日期 0 点对应的 UTC 时间；0 点落在夏令时跳变的空档里时取之后最早的有效时刻
+fn start_of_local_day(tz: Tz, date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("0 点总是有效");
+    (0..=3)
+        .find_map(|hours| tz.from_local_datetime(&(midnight + Duration::hours(hours))).earliest())
+        .expect("时区跳变不会超过 3 小时")
+        .with_timezone(&Utc)
+}
+
+/// 截止日期筛选对应的 UTC 区间 [from, until)；逾期没有下界
+/// 时区无效时按 UTC 处理 (保存时已校验，这里只是兜底)
+pub fn due_range(
+    filter: DueFilter,
+    timezone: &str,
+    week_start_day: i16,
+    now: DateTime<Utc>,
+) -> (Option<DateTime<Utc>>, DateTime<Utc>) {
+    let tz: Tz = timezone.parse().unwrap_or(Tz::UTC);
+    let today = now.with_timezone(&tz).date_naive();
+    match filter {
+        DueFilter::Overdue => (None, start_of_local_day(tz, today)),
+        DueFilter::Today => (
+            Some(start_of_local_day(tz, today)),
+            start_of_local_day(tz, today + Duration::days(1)),
+        ),
+        DueFilter::Week => {
+            let weekday = today.weekday().num_days_from_sunday() as i64;
+            let week_start = today - Duration::days((weekday - week_start_day as i64).rem_euclid(7));
+            (
+                Some(start_of_local_day(tz, week_start)),
+                start_of_local_day(tz, week_start + Duration::days(7)),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn today_follows_the_user_timezone() {
+        // UTC 的 10 月 16 日 20:00 在上海已是 10 月 17 日
+        let now = utc("2026-10-16T20:00:00Z");
+        assert_eq!(
+            due_range(DueFilter::Today, "Asia/Shanghai", 1, now),
+            (Some(utc("2026-10-16T16:00:00Z")), utc("2026-10-17T16:00:00Z"))
+        );
+        assert_eq!(
+            due_range(DueFilter::Today, "UTC", 1, now),
+            (Some(utc("2026-10-16T00:00:00Z")), utc("2026-10-17T00:00:00Z"))
+        );
+        assert_eq!(due_range(DueFilter::Overdue, "Asia/Shanghai", 1, now), (None, utc("2026-10-16T16:00:00Z")));
+    }
+
+    #[test]
+    fn week_starts_on_the_configured_day() {
+        // 2026-10-17 是周六
+        let now = utc("2026-10-17T12:00:00Z");
+        assert_eq!(
+            due_range(DueFilter::Week, "UTC", 1, now),
+            (Some(utc("2026-10-12T00:00:00Z")), utc("2026-10-19T00:00:00Z"))
+        );
+        assert_eq!(
+            due_range(DueFilter::Week, "UTC", 0, now),
+            (Some(utc("2026-10-11T00:00:00Z")), utc("2026-10-18T00:00:00Z"))
+        );
+        assert_eq!(
+            due_range(DueFilter::Week, "UTC", 6, now),
+            (Some(utc("2026-10-17T00:00:00Z")), utc("2026-10-24T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn days_are_shortened_by_daylight_saving() {
+        // 纽约 2026-03-08 凌晨 2 点跳到 3 点，这一天只有 23 小时
+        let (from, until) = due_range(DueFilter::Today, "America/New_York", 1, utc("2026-03-08T12:00:00Z"));
+        assert_eq!(from, Some(utc("2026-03-08T05:00:00Z")));
+        assert_eq!(until, utc("2026-03-09T04:00:00Z"));
+    }
+}
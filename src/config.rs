@@ -0,0 +1,216 @@
+// src/config.rs
+// 运行时配置 (来自环境变量 / .env)，启动时加载一次
+use std::net::IpAddr;
+use std::str::FromStr;
+
+pub struct Config {
+    /// 前端地址，用于拼接邮件中的链接
+    pub app_base_url: String,
+    /// 新密码哈希使用的 Argon2id 参数；调高后旧哈希会在用户下次登录时自动升级
+    pub argon2: argon2::Params,
+    /// 未验证邮箱的用户受到的限制
+    pub email_verification: EmailVerificationPolicy,
+    /// 申请注销后账户保留 (可恢复) 的天数，0 表示立即删除
+    pub account_deletion_grace_days: u32,
+    /// 谁可以注册新账户 (包括第三方登录首次自动创建账户)
+    pub registration: RegistrationPolicy,
+    /// 可信反向代理的地址段；只有 TCP 对端属于其中时才读取 X-Forwarded-For
+    pub trusted_proxies: Vec<IpNetwork>,
+}
+
+/// 一个 IP 地址段 (如 10.0.0.0/8)，不写前缀长度时表示单个地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 映射的 IPv6 地址 (::ffff:a.b.c.d) 按 IPv4 比较
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            v4 => v4,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| format!("无效的 IP 地址: {}", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("无效的前缀长度: {}", s))?,
+            None => max,
+        };
+        // 以 IPv4 映射形式 (::ffff:a.b.c.d/96 以上) 写的网段按 IPv4 保存，与 contains 的比较方式一致
+        match addr {
+            IpAddr::V6(v6) if prefix >= 96 => match v6.to_ipv4_mapped() {
+                Some(v4) => Ok(IpNetwork { addr: IpAddr::V4(v4), prefix: prefix - 96 }),
+                None => Ok(IpNetwork { addr, prefix }),
+            },
+            _ => Ok(IpNetwork { addr, prefix }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationPolicy {
+    /// 任何人都可以注册 (默认)
+    Open,
+    /// 需要管理员或已有用户创建的邀请码
+    InviteOnly,
+    /// 关闭注册
+    Closed,
+}
+
+/// 邮箱验证策略，按限制程度从低到高排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EmailVerificationPolicy {
+    /// 不强制验证 (默认)，注册时邮箱可选
+    Optional,
+    /// 未验证的用户可以登录，但不能创建/修改/删除项目、任务和评论
+    RequiredForWrites,
+    /// 未验证的用户不能登录
+    RequiredForLogin,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, String> {
+        let app_base_url = std::env::var("APP_BASE_URL")
+            .unwrap_or_else(|_| "http://localhost:5173".into())
+            .trim_end_matches('/')
+            .to_string();
+
+        // 未配置时使用 argon2 库的默认值 (19 MiB / 2 次迭代 / 1 并行度)
+        let argon2 = argon2::Params::new(
+            env_u32("ARGON2_MEMORY_KIB", argon2::Params::DEFAULT_M_COST)?,
+            env_u32("ARGON2_ITERATIONS", argon2::Params::DEFAULT_T_COST)?,
+            env_u32("ARGON2_PARALLELISM", argon2::Params::DEFAULT_P_COST)?,
+            None,
+        )
+        .map_err(|e| format!("Argon2 参数无效: {}", e))?;
+
+        // optional (默认) | writes | login
+        let email_verification = match std::env::var("EMAIL_VERIFICATION").as_deref() {
+            Ok("optional") | Err(_) => EmailVerificationPolicy::Optional,
+            Ok("writes") => EmailVerificationPolicy::RequiredForWrites,
+            Ok("login") => EmailVerificationPolicy::RequiredForLogin,
+            Ok(other) => return Err(format!("不支持的 EMAIL_VERIFICATION: {}", other)),
+        };
+
+        let account_deletion_grace_days = env_u32("ACCOUNT_DELETION_GRACE_DAYS", 30)?;
+
+        // open (默认) | invite | closed
+        let registration = match std::env::var("REGISTRATION_MODE").as_deref() {
+            Ok("open") | Err(_) => RegistrationPolicy::Open,
+            Ok("invite") => RegistrationPolicy::InviteOnly,
+            Ok("closed") => RegistrationPolicy::Closed,
+            Ok(other) => return Err(format!("不支持的 REGISTRATION_MODE: {}", other)),
+        };
+
+        // 逗号分隔的地址或地址段，未配置时不信任任何代理
+        let trusted_proxies = std::env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse().map_err(|e| format!("TRUSTED_PROXIES 格式错误: {}", e)))
+            .collect::<Result<Vec<IpNetwork>, String>>()?;
+
+        Ok(Config {
+            app_base_url,
+            argon2,
+            email_verification,
+            account_deletion_grace_days,
+            registration,
+            trusted_proxies,
+        })
+    }
+}
+
+fn env_u32(name: &str, default: u32) -> Result<u32, String> {
+    match std::env::var(name) {
+        Ok(value) => value.parse().map_err(|_| format!("{} 格式错误", name)),
+        Err(_) => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn net(s: &str) -> IpNetwork {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn rejects_bad_addresses_and_prefixes() {
+        for bad in ["", "10.0.0", "10.0.0.0/", "10.0.0.0/-1", "10.0.0.0/33", "10.0.0.0/300", "::1/129", "10.0.0.0/8/8", "host/8"] {
+            assert!(bad.parse::<IpNetwork>().is_err(), "{} 应被拒绝", bad);
+        }
+    }
+
+    #[test]
+    fn single_address_without_prefix() {
+        assert_eq!(net("10.1.2.3"), net("10.1.2.3/32"));
+        assert_eq!(net("::1"), net("::1/128"));
+        assert!(net("10.1.2.3").contains(ip("10.1.2.3")));
+        assert!(!net("10.1.2.3").contains(ip("10.1.2.4")));
+    }
+
+    #[test]
+    fn prefix_masks() {
+        let private = net("10.0.0.0/8");
+        assert!(private.contains(ip("10.255.0.1")));
+        assert!(!private.contains(ip("11.0.0.1")));
+        // 网段地址中主机位不为 0 时按前缀比较
+        assert!(net("192.168.1.77/24").contains(ip("192.168.1.1")));
+
+        let all_v4 = net("0.0.0.0/0");
+        assert!(all_v4.contains(ip("203.0.113.9")));
+        assert!(!all_v4.contains(ip("2001:db8::1")));
+        assert!(net("::/0").contains(ip("2001:db8::1")));
+
+        let v6 = net("2001:db8::/32");
+        assert!(v6.contains(ip("2001:db8:ffff::1")));
+        assert!(!v6.contains(ip("2001:db9::1")));
+    }
+
+    #[test]
+    fn ipv4_mapped_ipv6_is_compared_as_ipv4() {
+        assert!(net("10.0.0.0/8").contains(ip("::ffff:10.1.2.3")));
+        assert!(!net("10.0.0.0/8").contains(ip("::ffff:11.1.2.3")));
+        assert!(net("127.0.0.1").contains(ip("::ffff:127.0.0.1")));
+
+        // 以映射形式配置的网段同样生效
+        assert_eq!(net("::ffff:10.0.0.0/104"), net("10.0.0.0/8"));
+        assert!(net("::ffff:10.0.0.0/104").contains(ip("10.9.9.9")));
+        assert!(net("::ffff:127.0.0.1").contains(ip("::ffff:127.0.0.1")));
+    }
+}
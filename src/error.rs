@@ -1,6 +1,6 @@
 // src/error.rs
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -31,10 +31,23 @@ pub enum AppError {
 
     #[error("Bad request: {0}")]
     BadRequest(String),
+
+    // 资源冲突 (409)，如用户名/邮箱已被占用
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    // 登录尝试过于频繁 (429)，参数为建议等待的秒数
+    #[error("Too many requests, retry after {0}s")]
+    TooManyRequests(u64),
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let retry_after = match self {
+            AppError::TooManyRequests(secs) => Some(secs),
+            _ => None,
+        };
+
         let (status, message, details) = match self {
             AppError::Database(ref e) => {
                 tracing::error!("DB Error: {:?}", e);
@@ -75,6 +88,12 @@ impl IntoResponse for AppError {
                 )
             }
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg, None),
+            AppError::Conflict(msg) => (StatusCode::CONFLICT, msg, None),
+            AppError::TooManyRequests(secs) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("尝试次数过多，请 {} 秒后再试", secs),
+                None,
+            ),
             AppError::Internal => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "服务器内部错误".to_string(),
@@ -88,6 +107,10 @@ impl IntoResponse for AppError {
             "errors": details
         }));
 
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        if let Some(secs) = retry_after {
+            response.headers_mut().insert(header::RETRY_AFTER, secs.into());
+        }
+        response
     }
 }
\ No newline at end of file
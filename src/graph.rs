@@ -0,0 +1,176 @@
+// src/graph.rs
+// 任务依赖图：基于 "blocks" 关系的拓扑排序与关键路径
+// 写入关系时已做环检测；若数据中仍有环，环上的任务不会出现在结果中
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::models::IssueGraphNode;
+
+/// 排序键：截止日期早的优先，没有截止日期的排在最后，再按 ID
+fn urgency(node: &IssueGraphNode) -> (bool, Option<DateTime<Utc>>, i32) {
+    (node.due_date.is_none(), node.due_date, node.id)
+}
+
+/// Kahn 拓扑排序；同时可执行的任务按截止日期先后排列
+/// edges 为 (阻塞方, 被阻塞方)
+pub fn topological_order(nodes: &[IssueGraphNode], edges: &[(i32, i32)]) -> Vec<i32> {
+    let by_id: HashMap<i32, &IssueGraphNode> = nodes.iter().map(|n| (n.id, n)).collect();
+    let mut in_degree: HashMap<i32, usize> = nodes.iter().map(|n| (n.id, 0)).collect();
+    let mut successors: HashMap<i32, Vec<i32>> = HashMap::new();
+    for &(from, to) in edges {
+        if by_id.contains_key(&from) && by_id.contains_key(&to) {
+            successors.entry(from).or_default().push(to);
+            *in_degree.get_mut(&to).unwrap() += 1;
+        }
+    }
+
+    let mut ready: BTreeMap<(bool, Option<DateTime<Utc>>, i32), i32> = in_degree
+        .iter()
+        .filter(|(_, &d)| d == 0)
+        .map(|(id, _)| (urgency(by_id[id]), *id))
+        .collect();
+    let mut order = Vec::with_capacity(nodes.len());
+    while let Some((_, id)) = ready.pop_first() {
+        order.push(id);
+        for next in successors.get(&id).into_iter().flatten() {
+            let degree = in_degree.get_mut(next).unwrap();
+            *degree -= 1;
+            if *degree == 0 {
+                ready.insert(urgency(by_id[next]), *next);
+            }
+        }
+    }
+    order
+}
+
+/// 关键路径：决定未完成任务最晚完成时间的阻塞链 (不考虑已完成/已取消的任务)
+/// 任务的预计完成时间 = max(自身截止日期, 前置任务的预计完成时间)，没有截止日期的任务沿用前置任务的完成时间；
+/// 从预计完成最晚的任务出发，沿着完成最晚的前置任务回溯，即排期上决定整体完成时间的一条链
+/// 完成时间相同 (包括都没有截止日期) 时取更长的链，再取 ID 小的
+pub fn critical_path(nodes: &[IssueGraphNode], edges: &[(i32, i32)]) -> Vec<i32> {
+    let open: Vec<IssueGraphNode> = nodes.iter().filter(|n| !n.status_category.is_closed()).cloned().collect();
+    let by_id: HashMap<i32, &IssueGraphNode> = open.iter().map(|n| (n.id, n)).collect();
+    let open_edges: Vec<(i32, i32)> = edges
+        .iter()
+        .copied()
+        .filter(|(from, to)| by_id.contains_key(from) && by_id.contains_key(to))
+        .collect();
+    let mut predecessors: HashMap<i32, Vec<i32>> = HashMap::new();
+    for &(from, to) in &open_edges {
+        predecessors.entry(to).or_default().push(from);
+    }
+
+    // 按拓扑顺序递推：每个任务的 (预计完成时间, 链长) 与决定它的前置任务
+    let mut finish: HashMap<i32, (Option<DateTime<Utc>>, usize)> = HashMap::new();
+    let mut previous: HashMap<i32, i32> = HashMap::new();
+    let latest = |a: &(&(Option<DateTime<Utc>>, usize), i32), b: &(&(Option<DateTime<Utc>>, usize), i32)| {
+        a.0.cmp(b.0).then_with(|| b.1.cmp(&a.1))
+    };
+    for id in topological_order(&open, &open_edges) {
+        let due = by_id[&id].due_date;
+        let driving = predecessors
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter_map(|p| finish.get(p).map(|f| (f, *p)))
+            .max_by(latest)
+            .map(|(&(at, len), p)| (at, len, p));
+        match driving {
+            Some((at, len, pred)) => {
+                finish.insert(id, (due.max(at), len + 1));
+                previous.insert(id, pred);
+            }
+            None => {
+                finish.insert(id, (due, 1));
+            }
+        }
+    }
+
+    let end = finish.iter().map(|(id, f)| (f, *id)).max_by(latest).map(|(_, id)| id);
+    let mut path: Vec<i32> = std::iter::successors(end, |id| previous.get(id).copied()).collect();
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::StatusCategory;
+    use chrono::TimeZone;
+
+    fn node(id: i32, due_day: Option<u32>) -> IssueGraphNode {
+        IssueGraphNode {
+            id,
+            title: format!("issue {}", id),
+            status: "todo".into(),
+            status_category: StatusCategory::Unstarted,
+            due_date: due_day.map(|d| Utc.with_ymd_and_hms(2026, 1, d, 0, 0, 0).unwrap()),
+            blocked: false,
+        }
+    }
+
+    fn closed(id: i32) -> IssueGraphNode {
+        IssueGraphNode { status: "done".into(), status_category: StatusCategory::Completed, ..node(id, None) }
+    }
+
+    #[test]
+    fn chain() {
+        let nodes = [node(3, None), node(1, None), node(2, None)];
+        let edges = [(1, 2), (2, 3)];
+        assert_eq!(topological_order(&nodes, &edges), [1, 2, 3]);
+        assert_eq!(critical_path(&nodes, &edges), [1, 2, 3]);
+    }
+
+    #[test]
+    fn diamond_follows_the_branch_that_finishes_last() {
+        let nodes = [node(1, None), node(2, Some(20)), node(3, Some(10)), node(4, None)];
+        let edges = [(1, 2), (1, 3), (2, 4), (3, 4)];
+        assert_eq!(topological_order(&nodes, &edges), [1, 3, 2, 4]);
+        // 4 要等 2 在 20 日完成后才能完成，决定整体完成时间的是 2 这一支
+        assert_eq!(critical_path(&nodes, &edges), [1, 2, 4]);
+    }
+
+    #[test]
+    fn latest_finish_beats_a_longer_chain() {
+        let nodes = [node(1, Some(5)), node(2, Some(6)), node(3, Some(7)), node(4, None), node(5, Some(20))];
+        let edges = [(1, 2), (2, 3), (4, 5)];
+        assert_eq!(critical_path(&nodes, &edges), [4, 5]);
+
+        // 没有截止日期的任务沿用前置任务的完成时间
+        let nodes = [node(1, Some(25)), node(2, None), node(3, Some(20))];
+        assert_eq!(critical_path(&nodes, &[(1, 2)]), [1, 2]);
+    }
+
+    #[test]
+    fn cycle_in_data_is_left_out() {
+        let nodes = [node(1, None), node(2, None), node(3, None), node(4, None)];
+        let edges = [(1, 2), (2, 3), (3, 2)];
+        assert_eq!(topological_order(&nodes, &edges), [1, 4]);
+        assert_eq!(critical_path(&nodes, &edges), [1]);
+    }
+
+    #[test]
+    fn ties_are_broken_by_due_date_then_id() {
+        let nodes = [node(1, None), node(2, Some(5)), node(3, Some(2)), node(4, Some(5))];
+        assert_eq!(topological_order(&nodes, &[]), [3, 2, 4, 1]);
+
+        // 都没有截止日期时取最长的链，等长时取 ID 小的
+        let nodes = [node(1, None), node(2, None), node(3, None), node(4, None), node(5, None)];
+        assert_eq!(critical_path(&nodes, &[(1, 2), (3, 4), (4, 5)]), [3, 4, 5]);
+        assert_eq!(critical_path(&nodes, &[(1, 2), (3, 4)]), [1, 2]);
+    }
+
+    #[test]
+    fn closed_issues_are_not_on_the_critical_path() {
+        let nodes = [closed(1), node(2, None), node(3, None), node(4, None), closed(5)];
+        let edges = [(1, 2), (2, 3), (3, 4), (4, 5)];
+        assert_eq!(topological_order(&nodes, &edges), [1, 2, 3, 4, 5]);
+        assert_eq!(critical_path(&nodes, &edges), [2, 3, 4]);
+    }
+
+    #[test]
+    fn empty_graph() {
+        assert!(topological_order(&[], &[]).is_empty());
+        assert!(critical_path(&[closed(1)], &[]).is_empty());
+    }
+}
@@ -1,275 +1,3673 @@
 // src/handlers.rs
-use crate::auth::{create_jwt, hash_password, verify_password, AuthUser, generate_refresh_token};
+use crate::access::{
+    authorize_issue, authorize_label, authorize_project, authorize_workspace, project_role_of, release_account_ownership, ProjectRole, WorkspaceRole, WorkspaceScope, PROJECT_USERS_SQL, VISIBLE_PROJECTS_SQL,
+};
+use crate::auth::{
+    create_email_verification_token, create_jwt, create_mfa_token, dummy_password_hash, generate_personal_access_token,
+    generate_refresh_token, generate_secure_token, hash_password, password_needs_rehash, hash_token, verify_email_verification_token, verify_mfa_token, verify_password, AdminUser, AuthUser,
+    ClientInfo, Credential, Role, Scope, EMAIL_VERIFICATION_TTL_HOURS, MFA_TOKEN_TTL_MINUTES, REFRESH_TOKEN_REUSED,
+    REFRESH_TOKEN_TTL_DAYS,
+};
+use crate::calendar;
+use crate::config::{EmailVerificationPolicy, RegistrationPolicy};
+use crate::graph;
+use crate::mailer::Email;
 use crate::models::*;
+use crate::oidc::{IdTokenClaims, OidcClient};
+use crate::rank;
+use crate::throttle::ThrottleKey;
+use crate::totp;
 use crate::AppError;
 use crate::AppState;
 use crate::validation::ValidatedJson;
+use crate::workflow;
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{header, StatusCode},
     Json,
 };
+use argon2::password_hash::rand_core::{OsRng, RngCore};
 use serde_json::json;
-use chrono::{Utc, Duration};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// ======= WORKSPACE HANDLERS =======
+
+const WORKSPACE_MEMBER_COLUMNS: &str = r#"
+    m.user_id, u.username, u.display_name, u.avatar_url, m.role, m.created_at"#;
+
+/// 每个用户注册时自动创建个人工作区 (不能邀请成员或删除)
+async fn create_personal_workspace(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: i32,
+    username: &str,
+) -> Result<(), AppError> {
+    let (workspace_id,): (i32,) =
+        sqlx::query_as("INSERT INTO workspaces (name, personal_user_id) VALUES ($1, $2) RETURNING id")
+            .bind(format!("{} 的个人空间", username))
+            .bind(user_id)
+            .fetch_one(&mut **tx)
+            .await?;
+    sqlx::query("INSERT INTO workspace_members (workspace_id, user_id, role) VALUES ($1, $2, $3)")
+        .bind(workspace_id)
+        .bind(user_id)
+        .bind(WorkspaceRole::Owner)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+async fn fetch_workspace(state: &AppState, workspace_id: i32) -> Result<Workspace, AppError> {
+    sqlx::query_as::<_, Workspace>("SELECT * FROM workspaces WHERE id = $1")
+        .bind(workspace_id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("工作区不存在或无权访问".into()))
+}
+
+async fn fetch_workspace_member(state: &AppState, workspace_id: i32, user_id: i32) -> Result<WorkspaceMember, AppError> {
+    sqlx::query_as::<_, WorkspaceMember>(&format!(
+        "SELECT {} FROM workspace_members m JOIN users u ON m.user_id = u.id WHERE m.workspace_id = $1 AND m.user_id = $2",
+        WORKSPACE_MEMBER_COLUMNS
+    ))
+    .bind(workspace_id)
+    .bind(user_id)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| AppError::NotFound("成员不存在".into()))
+}
+
+pub async fn get_workspaces_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<MyWorkspace>>, AppError> {
+    user.require_scope(Scope::ProjectsRead)?;
+    let workspaces = sqlx::query_as::<_, MyWorkspace>(
+        r#"SELECT w.*, m.role FROM workspaces w
+           JOIN workspace_members m ON m.workspace_id = w.id AND m.user_id = $1
+           ORDER BY w.personal_user_id IS NULL, w.created_at"#,
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(workspaces))
+}
+
+pub async fn create_workspace_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<CreateWorkspaceSchema>,
+) -> Result<Json<MyWorkspace>, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+
+    // 创建者成为工作区所有者
+    let mut tx = state.db.begin().await?;
+    let workspace = sqlx::query_as::<_, Workspace>(
+        "INSERT INTO workspaces (name, timezone) VALUES ($1, COALESCE($2, 'UTC')) RETURNING *",
+    )
+    .bind(body.name)
+    .bind(body.timezone)
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query("INSERT INTO workspace_members (workspace_id, user_id, role) VALUES ($1, $2, $3)")
+        .bind(workspace.id)
+        .bind(user.id)
+        .bind(WorkspaceRole::Owner)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(Json(MyWorkspace { workspace, role: WorkspaceRole::Owner }))
+}
+
+pub async fn get_workspace_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<MyWorkspace>, AppError> {
+    user.require_scope(Scope::ProjectsRead)?;
+    let role = authorize_workspace(&state.db, &user, id, WorkspaceRole::Member).await?;
+    Ok(Json(MyWorkspace { workspace: fetch_workspace(&state, id).await?, role }))
+}
+
+/// 修改工作区名称与设置 (管理员及以上)
+pub async fn update_workspace_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<UpdateWorkspaceSchema>,
+) -> Result<Json<MyWorkspace>, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+    let role = authorize_workspace(&state.db, &user, id, WorkspaceRole::Admin).await?;
+
+    let trim_all = |names: Vec<String>| names.into_iter().map(|n| n.trim().to_string()).collect::<Vec<_>>();
+    let workspace = sqlx::query_as::<_, Workspace>(
+        r#"UPDATE workspaces SET
+            name = COALESCE($1, name),
+            timezone = COALESCE($2, timezone),
+            default_statuses = COALESCE($3, default_statuses),
+            default_labels = COALESCE($4, default_labels)
+         WHERE id = $5
+         RETURNING *"#,
+    )
+    .bind(body.name)
+    .bind(body.timezone)
+    .bind(body.default_statuses.map(trim_all))
+    .bind(body.default_labels.map(trim_all))
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| AppError::NotFound("工作区不存在或无权访问".into()))?;
+
+    Ok(Json(MyWorkspace { workspace, role }))
+}
+
+/// 删除团队工作区及其下所有项目 (仅所有者)
+pub async fn delete_workspace_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+    authorize_workspace(&state.db, &user, id, WorkspaceRole::Owner).await?;
+    let res = sqlx::query("DELETE FROM workspaces WHERE id = $1 AND personal_user_id IS NULL")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::BadRequest("个人工作区不能删除".into()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_workspace_members_handler(
+    user: AuthUser,
+    Path(workspace_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<WorkspaceMember>>, AppError> {
+    user.require_scope(Scope::ProjectsRead)?;
+    authorize_workspace(&state.db, &user, workspace_id, WorkspaceRole::Member).await?;
+    let members = sqlx::query_as::<_, WorkspaceMember>(&format!(
+        r#"SELECT {} FROM workspace_members m JOIN users u ON m.user_id = u.id
+           WHERE m.workspace_id = $1 ORDER BY m.created_at"#,
+        WORKSPACE_MEMBER_COLUMNS
+    ))
+    .bind(workspace_id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(members))
+}
+
+/// 按用户名邀请成员 (管理员及以上)；个人工作区不能邀请成员
+pub async fn add_workspace_member_handler(
+    user: AuthUser,
+    Path(workspace_id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<AddWorkspaceMemberSchema>,
+) -> Result<Json<WorkspaceMember>, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+    authorize_workspace(&state.db, &user, workspace_id, WorkspaceRole::Admin).await?;
+    if body.role == WorkspaceRole::Owner {
+        return Err(AppError::BadRequest("不能直接添加所有者".into()));
+    }
+    if fetch_workspace(&state, workspace_id).await?.personal_user_id.is_some() {
+        return Err(AppError::BadRequest("个人工作区不能邀请成员".into()));
+    }
+
+    let (member_id,): (i32,) = sqlx::query_as("SELECT id FROM users WHERE username = $1 AND disabled_at IS NULL")
+        .bind(&body.username)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("用户不存在".into()))?;
+
+    let inserted = sqlx::query(
+        r#"INSERT INTO workspace_members (workspace_id, user_id, role, added_by) VALUES ($1, $2, $3, $4)
+           ON CONFLICT (workspace_id, user_id) DO NOTHING"#,
+    )
+    .bind(workspace_id)
+    .bind(member_id)
+    .bind(body.role)
+    .bind(user.id)
+    .execute(&state.db)
+    .await?;
+    if inserted.rows_affected() == 0 {
+        return Err(AppError::Conflict("该用户已是工作区成员".into()));
+    }
+
+    Ok(Json(fetch_workspace_member(&state, workspace_id, member_id).await?))
+}
+
+/// 修改成员角色 (管理员及以上，不能修改所有者)
+pub async fn update_workspace_member_handler(
+    user: AuthUser,
+    Path((workspace_id, member_id)): Path<(i32, i32)>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<UpdateWorkspaceMemberSchema>,
+) -> Result<Json<WorkspaceMember>, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+    authorize_workspace(&state.db, &user, workspace_id, WorkspaceRole::Admin).await?;
+    if body.role == WorkspaceRole::Owner {
+        return Err(AppError::BadRequest("不能直接设置所有者".into()));
+    }
+
+    let res = sqlx::query(
+        "UPDATE workspace_members SET role = $1 WHERE workspace_id = $2 AND user_id = $3 AND role <> 'owner'",
+    )
+    .bind(body.role)
+    .bind(workspace_id)
+    .bind(member_id)
+    .execute(&state.db)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound("成员不存在或不能修改所有者".into()));
+    }
+
+    Ok(Json(fetch_workspace_member(&state, workspace_id, member_id).await?))
+}
+
+/// 移除成员：管理员可以移除任何非所有者成员，其他成员可以退出工作区
+pub async fn remove_workspace_member_handler(
+    user: AuthUser,
+    Path((workspace_id, member_id)): Path<(i32, i32)>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+    let min_role = if member_id == user.id { WorkspaceRole::Member } else { WorkspaceRole::Admin };
+    authorize_workspace(&state.db, &user, workspace_id, min_role).await?;
+
+    let mut tx = state.db.begin().await?;
+    let res = sqlx::query("DELETE FROM workspace_members WHERE workspace_id = $1 AND user_id = $2 AND role <> 'owner'")
+        .bind(workspace_id)
+        .bind(member_id)
+        .execute(&mut *tx)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound("成员不存在或不能移除所有者".into()));
+    }
+    unassign_inaccessible_issues(&mut tx, member_id).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
 // ======= PROJECTS HANDLERS =======
 
 pub async fn get_projects_handler(
     user: AuthUser,
+    WorkspaceScope(workspace_id): WorkspaceScope,
     State(state): State<AppState>,
 ) -> Result<Json<Vec<Project>>, AppError> {
-    let projects = sqlx::query_as::<_, Project>(
-        "SELECT * FROM projects WHERE user_id = $1 ORDER BY updated_at DESC",
+    user.require_scope(Scope::ProjectsRead)?;
+    let projects = sqlx::query_as::<_, Project>(&format!(
+        r#"SELECT p.* FROM projects p
+           WHERE p.id IN ({})
+             AND ($2::INTEGER IS NULL OR p.workspace_id = $2)
+           ORDER BY p.updated_at DESC"#,
+        VISIBLE_PROJECTS_SQL
+    ))
+    .bind(user.id)
+    .bind(workspace_id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(projects))
+}
+
+pub async fn create_project_handler(
+    user: AuthUser,
+    WorkspaceScope(scope): WorkspaceScope,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<CreateProjectSchema>,
+) -> Result<Json<Project>, AppError> {
+    user.require_scope(Scope::ProjectsWrite)?;
+    user.require_verified_email(&state).await?;
+
+    // 未指定工作区时放入个人工作区
+    let workspace_id = match body.workspace_id.or(scope) {
+        Some(id) => id,
+        None => {
+            let (id,): (i32,) = sqlx::query_as("SELECT id FROM workspaces WHERE personal_user_id = $1")
+                .bind(user.id)
+                .fetch_optional(&state.db)
+                .await?
+                .ok_or_else(|| AppError::BadRequest("请指定工作区".into()))?;
+            id
+        }
+    };
+    authorize_workspace(&state.db, &user, workspace_id, WorkspaceRole::Member).await?;
+
+    // 创建者自动成为项目所有者
+    let mut tx = state.db.begin().await?;
+    let project = sqlx::query_as::<_, Project>(
+        "INSERT INTO projects (workspace_id, user_id, name, description, color) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+    )
+    .bind(workspace_id)
+    .bind(user.id)
+    .bind(body.name)
+    .bind(body.description)
+    .bind(body.color.unwrap_or_else(|| "#5E6AD2".to_string()))
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query("INSERT INTO project_members (project_id, user_id, role) VALUES ($1, $2, $3)")
+        .bind(project.id)
+        .bind(user.id)
+        .bind(ProjectRole::Owner)
+        .execute(&mut *tx)
+        .await?;
+    // 按工作区默认标签与默认状态初始化项目标签和工作流
+    sqlx::query("INSERT INTO labels (project_id, name) SELECT $1, UNNEST(default_labels) FROM workspaces WHERE id = $2")
+        .bind(project.id)
+        .bind(workspace_id)
+        .execute(&mut *tx)
+        .await?;
+    let (default_statuses,): (Vec<String>,) = sqlx::query_as("SELECT default_statuses FROM workspaces WHERE id = $1")
+        .bind(workspace_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    for (position, (name, category)) in workflow::default_states(&default_statuses).into_iter().enumerate() {
+        sqlx::query("INSERT INTO workflow_states (project_id, name, category, position) VALUES ($1, $2, $3, $4)")
+            .bind(project.id)
+            .bind(name)
+            .bind(category)
+            .bind(position as i32)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(Json(project))
+}
+
+pub async fn update_project_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<UpdateProjectSchema>,
+) -> Result<Json<Project>, AppError> {
+    user.require_scope(Scope::ProjectsWrite)?;
+    user.require_verified_email(&state).await?;
+    authorize_project(&state.db, &user, id, ProjectRole::Editor).await?;
+    let project = sqlx::query_as::<_, Project>(
+        r#"UPDATE projects SET 
+            name = COALESCE($1, name),
+            description = COALESCE($2, description),
+            status = COALESCE($3, status),
+            color = COALESCE($4, color),
+            updated_at = NOW()
+         WHERE id = $5
+         RETURNING *"#,
+    )
+    .bind(body.name).bind(body.description).bind(body.status).bind(body.color)
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| AppError::NotFound("项目未找到".into()))?;
+
+    Ok(Json(project))
+}
+
+pub async fn delete_project_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+    authorize_project(&state.db, &user, id, ProjectRole::Owner).await?;
+    let res = sqlx::query("DELETE FROM projects WHERE id = $1")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    if res.rows_affected() == 0 { return Err(AppError::NotFound("项目不存在或无权操作".into())); }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ======= PROJECT MEMBER HANDLERS =======
+
+const PROJECT_MEMBER_COLUMNS: &str = r#"
+    m.user_id, u.username, u.display_name, u.avatar_url, m.role, m.created_at"#;
+
+async fn fetch_project_member(state: &AppState, project_id: i32, user_id: i32) -> Result<ProjectMember, AppError> {
+    sqlx::query_as::<_, ProjectMember>(&format!(
+        "SELECT {} FROM project_members m JOIN users u ON m.user_id = u.id WHERE m.project_id = $1 AND m.user_id = $2",
+        PROJECT_MEMBER_COLUMNS
+    ))
+    .bind(project_id)
+    .bind(user_id)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| AppError::NotFound("成员不存在".into()))
+}
+
+pub async fn get_project_members_handler(
+    user: AuthUser,
+    Path(project_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ProjectMember>>, AppError> {
+    user.require_scope(Scope::ProjectsRead)?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Viewer).await?;
+    let members = sqlx::query_as::<_, ProjectMember>(&format!(
+        r#"SELECT {} FROM project_members m JOIN users u ON m.user_id = u.id
+           WHERE m.project_id = $1 ORDER BY m.created_at"#,
+        PROJECT_MEMBER_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(members))
+}
+
+/// 按用户名邀请成员 (仅所有者)；所有者角色只能通过转移项目获得
+pub async fn add_project_member_handler(
+    user: AuthUser,
+    Path(project_id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<AddProjectMemberSchema>,
+) -> Result<Json<ProjectMember>, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Owner).await?;
+    if body.role == ProjectRole::Owner {
+        return Err(AppError::BadRequest("不能直接添加所有者".into()));
+    }
+
+    let (member_id,): (i32,) = sqlx::query_as("SELECT id FROM users WHERE username = $1 AND disabled_at IS NULL")
+        .bind(&body.username)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("用户不存在".into()))?;
+
+    let inserted = sqlx::query(
+        r#"INSERT INTO project_members (project_id, user_id, role, added_by) VALUES ($1, $2, $3, $4)
+           ON CONFLICT (project_id, user_id) DO NOTHING"#,
+    )
+    .bind(project_id)
+    .bind(member_id)
+    .bind(body.role)
+    .bind(user.id)
+    .execute(&state.db)
+    .await?;
+    if inserted.rows_affected() == 0 {
+        return Err(AppError::Conflict("该用户已是项目成员".into()));
+    }
+
+    Ok(Json(fetch_project_member(&state, project_id, member_id).await?))
+}
+
+/// 修改成员角色 (仅所有者)
+pub async fn update_project_member_handler(
+    user: AuthUser,
+    Path((project_id, member_id)): Path<(i32, i32)>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<UpdateProjectMemberSchema>,
+) -> Result<Json<ProjectMember>, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Owner).await?;
+    if body.role == ProjectRole::Owner {
+        return Err(AppError::BadRequest("不能直接设置所有者".into()));
+    }
+
+    let res = sqlx::query(
+        "UPDATE project_members SET role = $1 WHERE project_id = $2 AND user_id = $3 AND role <> 'owner'",
+    )
+    .bind(body.role)
+    .bind(project_id)
+    .bind(member_id)
+    .execute(&state.db)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound("成员不存在或不能修改所有者".into()));
+    }
+
+    Ok(Json(fetch_project_member(&state, project_id, member_id).await?))
+}
+
+/// 移除成员：所有者可以移除任何非所有者成员，其他成员可以退出项目
+pub async fn remove_project_member_handler(
+    user: AuthUser,
+    Path((project_id, member_id)): Path<(i32, i32)>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+    let min_role = if member_id == user.id { ProjectRole::Viewer } else { ProjectRole::Owner };
+    authorize_project(&state.db, &user, project_id, min_role).await?;
+
+    let mut tx = state.db.begin().await?;
+    let res = sqlx::query("DELETE FROM project_members WHERE project_id = $1 AND user_id = $2 AND role <> 'owner'")
+        .bind(project_id)
+        .bind(member_id)
+        .execute(&mut *tx)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound("成员不存在或不能移除所有者".into()));
+    }
+    unassign_inaccessible_issues(&mut tx, member_id).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 移除成员后，撤销该用户在已无法访问的项目中的任务指派
+async fn unassign_inaccessible_issues(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: i32,
+) -> Result<(), AppError> {
+    sqlx::query(&format!(
+        r#"DELETE FROM issue_assignees a USING issues i
+           WHERE a.issue_id = i.id AND a.user_id = $1
+             AND i.project_id NOT IN ({})"#,
+        VISIBLE_PROJECTS_SQL
+    ))
+    .bind(user_id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+// ======= PROJECT TRANSFER HANDLERS =======
+
+/// 转移请求的有效期
+const PROJECT_TRANSFER_TTL_DAYS: i64 = 7;
+
+const PROJECT_TRANSFER_SELECT: &str = r#"
+    SELECT t.id, t.project_id, p.name AS project_name,
+           t.from_user_id, fu.username AS from_username, t.to_user_id, tu.username AS to_username, t.requested_by,
+           CASE WHEN t.status = 'pending' AND t.expires_at <= NOW() THEN 'expired' ELSE t.status END AS status,
+           t.from_workspace_id, t.to_workspace_id, t.expires_at, t.responded_at, t.created_at
+    FROM project_transfers t
+    JOIN projects p ON p.id = t.project_id
+    LEFT JOIN users fu ON fu.id = t.from_user_id
+    JOIN users tu ON tu.id = t.to_user_id"#;
+
+async fn fetch_project_transfer(state: &AppState, transfer_id: i32) -> Result<ProjectTransfer, AppError> {
+    sqlx::query_as::<_, ProjectTransfer>(&format!("{} WHERE t.id = $1", PROJECT_TRANSFER_SELECT))
+        .bind(transfer_id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("转移请求不存在".into()))
+}
+
+/// 发起所有权转移 (所有者或工作区管理员)；接收人必须已能访问项目，确认后才生效
+pub async fn create_project_transfer_handler(
+    user: AuthUser,
+    Path(project_id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<CreateProjectTransferSchema>,
+) -> Result<Json<ProjectTransfer>, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Owner).await?;
+
+    let (to_user_id,): (i32,) = sqlx::query_as("SELECT id FROM users WHERE username = $1 AND disabled_at IS NULL")
+        .bind(&body.username)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("用户不存在".into()))?;
+    if project_role_of(&state.db, to_user_id, project_id).await?.is_none() {
+        return Err(AppError::BadRequest("只能转移给项目成员".into()));
+    }
+    let (owner_id,): (Option<i32>,) = sqlx::query_as("SELECT user_id FROM projects WHERE id = $1")
+        .bind(project_id)
+        .fetch_one(&state.db)
+        .await?;
+    if owner_id == Some(to_user_id) {
+        return Err(AppError::BadRequest("该用户已是项目所有者".into()));
+    }
+
+    // 过期的请求先标记为已过期 (无人响应，不写 responded_at)，再占用"每个项目一个待确认请求"的名额
+    sqlx::query("UPDATE project_transfers SET status = 'expired' WHERE project_id = $1 AND status = 'pending' AND expires_at <= NOW()")
+        .bind(project_id)
+        .execute(&state.db)
+        .await?;
+    let created: Option<(i32,)> = sqlx::query_as(
+        r#"INSERT INTO project_transfers (project_id, from_user_id, to_user_id, requested_by, expires_at)
+           VALUES ($1, $2, $3, $4, $5)
+           ON CONFLICT (project_id) WHERE status = 'pending' DO NOTHING
+           RETURNING id"#,
+    )
+    .bind(project_id)
+    .bind(owner_id)
+    .bind(to_user_id)
+    .bind(user.id)
+    .bind(Utc::now() + Duration::days(PROJECT_TRANSFER_TTL_DAYS))
+    .fetch_optional(&state.db)
+    .await?;
+    let (transfer_id,) = created.ok_or_else(|| AppError::Conflict("该项目已有待确认的转移请求".into()))?;
+
+    Ok(Json(fetch_project_transfer(&state, transfer_id).await?))
+}
+
+/// 撤回待确认的转移请求
+pub async fn cancel_project_transfer_handler(
+    user: AuthUser,
+    Path(project_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Owner).await?;
+    let res = sqlx::query(
+        r#"UPDATE project_transfers SET status = 'canceled', responded_at = NOW()
+           WHERE project_id = $1 AND status = 'pending' AND expires_at > NOW()"#,
+    )
+    .bind(project_id)
+    .execute(&state.db)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound("没有待确认的转移请求".into()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 项目的所有权转移历史
+pub async fn get_project_transfers_handler(
+    user: AuthUser,
+    Path(project_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ProjectTransfer>>, AppError> {
+    user.require_scope(Scope::ProjectsRead)?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Viewer).await?;
+    let transfers = sqlx::query_as::<_, ProjectTransfer>(&format!(
+        "{} WHERE t.project_id = $1 ORDER BY t.created_at DESC",
+        PROJECT_TRANSFER_SELECT
+    ))
+    .bind(project_id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(transfers))
+}
+
+/// 等待我确认的转移请求
+pub async fn get_incoming_transfers_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ProjectTransfer>>, AppError> {
+    user.require_scope(Scope::ProjectsRead)?;
+    let transfers = sqlx::query_as::<_, ProjectTransfer>(&format!(
+        "{} WHERE t.to_user_id = $1 AND t.status = 'pending' AND t.expires_at > NOW() ORDER BY t.created_at DESC",
+        PROJECT_TRANSFER_SELECT
+    ))
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(transfers))
+}
+
+/// 接收人确认转移：成为项目所有者，原所有者降为编辑者，必要时迁移工作区
+pub async fn accept_project_transfer_handler(
+    user: AuthUser,
+    Path(transfer_id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<AcceptProjectTransferSchema>,
+) -> Result<Json<ProjectTransfer>, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+
+    let mut tx = state.db.begin().await?;
+    let (project_id, from_user_id): (i32, Option<i32>) = sqlx::query_as(
+        r#"SELECT project_id, from_user_id FROM project_transfers
+           WHERE id = $1 AND to_user_id = $2 AND status = 'pending' AND expires_at > NOW()
+           FOR UPDATE"#,
+    )
+    .bind(transfer_id)
+    .bind(user.id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound("转移请求不存在或已失效".into()))?;
+
+    let (owner_id, from_workspace_id, personal_user_id): (Option<i32>, i32, Option<i32>) = sqlx::query_as(
+        r#"SELECT p.user_id, p.workspace_id, w.personal_user_id
+           FROM projects p JOIN workspaces w ON w.id = p.workspace_id
+           WHERE p.id = $1 FOR UPDATE OF p"#,
+    )
+    .bind(project_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if owner_id.is_none() || owner_id != from_user_id || project_role_of(&state.db, user.id, project_id).await?.is_none() {
+        return Err(AppError::Conflict("项目所有者或成员已变更，该转移请求已失效".into()));
+    }
+
+    // 原所有者个人工作区中的项目跟随所有者迁入接收人的个人工作区
+    let to_workspace_id = match body.workspace_id {
+        Some(workspace_id) => {
+            authorize_workspace(&state.db, &user, workspace_id, WorkspaceRole::Member).await?;
+            workspace_id
+        }
+        None if personal_user_id.is_some() && personal_user_id == owner_id => {
+            let (workspace_id,): (i32,) = sqlx::query_as("SELECT id FROM workspaces WHERE personal_user_id = $1")
+                .bind(user.id)
+                .fetch_one(&mut *tx)
+                .await?;
+            workspace_id
+        }
+        None => from_workspace_id,
+    };
+
+    sqlx::query("UPDATE project_members SET role = 'editor' WHERE project_id = $1 AND role = 'owner'")
+        .bind(project_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        r#"INSERT INTO project_members (project_id, user_id, role, added_by) VALUES ($1, $2, 'owner', $3)
+           ON CONFLICT (project_id, user_id) DO UPDATE SET role = 'owner'"#,
+    )
+    .bind(project_id)
+    .bind(user.id)
+    .bind(owner_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("UPDATE projects SET user_id = $1, workspace_id = $2, updated_at = NOW() WHERE id = $3")
+        .bind(user.id)
+        .bind(to_workspace_id)
+        .bind(project_id)
+        .execute(&mut *tx)
+        .await?;
+
+    // 迁移工作区后，失去项目访问权限的负责人不再保留指派
+    sqlx::query(
+        r#"DELETE FROM issue_assignees a USING issues i, projects p
+           WHERE a.issue_id = i.id AND i.project_id = p.id AND p.id = $1
+             AND NOT EXISTS (SELECT 1 FROM project_members pm WHERE pm.project_id = p.id AND pm.user_id = a.user_id)
+             AND NOT EXISTS (SELECT 1 FROM workspace_members wm WHERE wm.workspace_id = p.workspace_id AND wm.user_id = a.user_id)"#,
+    )
+    .bind(project_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"UPDATE project_transfers SET status = 'accepted', responded_at = NOW(), from_workspace_id = $1, to_workspace_id = $2
+           WHERE id = $3"#,
+    )
+    .bind(from_workspace_id)
+    .bind(to_workspace_id)
+    .bind(transfer_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(fetch_project_transfer(&state, transfer_id).await?))
+}
+
+/// 接收人拒绝转移
+pub async fn decline_project_transfer_handler(
+    user: AuthUser,
+    Path(transfer_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<ProjectTransfer>, AppError> {
+    user.require_scope(Scope::ProjectsAdmin)?;
+    user.require_verified_email(&state).await?;
+    let res = sqlx::query(
+        r#"UPDATE project_transfers SET status = 'declined', responded_at = NOW()
+           WHERE id = $1 AND to_user_id = $2 AND status = 'pending' AND expires_at > NOW()"#,
+    )
+    .bind(transfer_id)
+    .bind(user.id)
+    .execute(&state.db)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound("转移请求不存在或已失效".into()));
+    }
+    Ok(Json(fetch_project_transfer(&state, transfer_id).await?))
+}
+
+// ======= WORKFLOW HANDLERS =======
+
+async fn fetch_workflow(db: &sqlx::PgPool, project_id: i32) -> Result<ProjectWorkflow, AppError> {
+    let states = sqlx::query_as::<_, WorkflowState>(
+        "SELECT id, name, category, position, wip_limit, created_at FROM workflow_states WHERE project_id = $1 ORDER BY position",
+    )
+    .bind(project_id)
+    .fetch_all(db)
+    .await?;
+    let transitions = sqlx::query_as::<_, WorkflowTransition>(
+        r#"SELECT f.name AS from, s.name AS to FROM workflow_transitions t
+           JOIN workflow_states f ON f.id = t.from_state_id
+           JOIN workflow_states s ON s.id = t.to_state_id
+           WHERE t.project_id = $1
+           ORDER BY f.position, s.position"#,
+    )
+    .bind(project_id)
+    .fetch_all(db)
+    .await?;
+    Ok(ProjectWorkflow { states, transitions })
+}
+
+/// 状态在项目工作流中的分类，不存在时返回 400
+async fn workflow_state_category(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    project_id: i32,
+    name: &str,
+) -> Result<StatusCategory, AppError> {
+    let category: Option<(StatusCategory,)> =
+        sqlx::query_as("SELECT category FROM workflow_states WHERE project_id = $1 AND name = $2")
+            .bind(project_id)
+            .bind(name)
+            .fetch_optional(&mut **tx)
+            .await?;
+    category
+        .map(|(c,)| c)
+        .ok_or_else(|| AppError::BadRequest(format!("状态「{}」不在项目工作流中", name)))
+}
+
+pub async fn get_project_workflow_handler(
+    user: AuthUser,
+    Path(project_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<ProjectWorkflow>, AppError> {
+    user.require_scope(Scope::ProjectsRead)?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Viewer).await?;
+    Ok(Json(fetch_workflow(&state.db, project_id).await?))
+}
+
+/// 整体替换工作流 (仅所有者)：状态按 ID 或名称匹配已有状态，改名会同步到任务上
+pub async fn update_project_workflow_handler(
+    user: AuthUser,
+    Path(project_id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(mut body): ValidatedJson<UpdateWorkflowSchema>,
+) -> Result<Json<ProjectWorkflow>, AppError> {
+    user.require_scope(Scope::ProjectsWrite)?;
+    user.require_verified_email(&state).await?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Owner).await?;
+    workflow::normalize_workflow(&mut body)?;
+
+    let mut tx = state.db.begin().await?;
+    lock_project(&mut tx, project_id).await?;
+    let existing: Vec<(i32, String)> = sqlx::query_as("SELECT id, name FROM workflow_states WHERE project_id = $1")
+        .bind(project_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    // 每个输入状态对应的已有状态 ID (新状态为 None)
+    let mut matched: Vec<Option<i32>> = Vec::with_capacity(body.states.len());
+    for input in &body.states {
+        let id = match input.id {
+            Some(id) if existing.iter().any(|(e, _)| *e == id) => Some(id),
+            Some(id) => return Err(AppError::BadRequest(format!("状态 #{} 不属于该项目", id))),
+            None => existing.iter().find(|(_, name)| *name == input.name).map(|(id, _)| *id),
+        };
+        if id.is_some() && matched.contains(&id) {
+            return Err(AppError::BadRequest("同一状态出现了多次".into()));
+        }
+        matched.push(id);
+    }
+
+    // 删除未出现的状态，仍有任务使用时拒绝
+    let removed: Vec<(i32, String)> = existing.iter().filter(|(id, _)| !matched.contains(&Some(*id))).cloned().collect();
+    if !removed.is_empty() {
+        let removed_names: Vec<String> = removed.iter().map(|(_, name)| name.clone()).collect();
+        let in_use: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT status, COUNT(*) FROM issues WHERE project_id = $1 AND status = ANY($2) GROUP BY status ORDER BY status",
+        )
+        .bind(project_id)
+        .bind(&removed_names)
+        .fetch_all(&mut *tx)
+        .await?;
+        if !in_use.is_empty() {
+            let names: Vec<String> = in_use.iter().map(|(name, count)| format!("{} ({} 个)", name, count)).collect();
+            return Err(AppError::Conflict(format!(
+                "以下状态仍有任务使用，请先将任务移到其他状态: {}",
+                names.join("、")
+            )));
+        }
+        let removed_ids: Vec<i32> = removed.iter().map(|(id, _)| *id).collect();
+        sqlx::query("DELETE FROM workflow_states WHERE id = ANY($1)")
+            .bind(&removed_ids)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    // 改名的状态先换成临时名称，避免互换名称时违反唯一约束
+    let renamed: Vec<i32> = body
+        .states
+        .iter()
+        .zip(&matched)
+        .filter_map(|(input, id)| {
+            let id = (*id)?;
+            existing.iter().any(|(e, name)| *e == id && *name != input.name).then_some(id)
+        })
+        .collect();
+    if !renamed.is_empty() {
+        sqlx::query("UPDATE workflow_states SET name = '~' || id WHERE id = ANY($1)")
+            .bind(&renamed)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let mut ids: HashMap<String, i32> = HashMap::new();
+    for (position, (input, id)) in body.states.iter().zip(&matched).enumerate() {
+        let (id,): (i32,) = match id {
+            Some(id) => {
+                sqlx::query_as(
+                    "UPDATE workflow_states SET name = $1, category = $2, position = $3, wip_limit = $4 WHERE id = $5 RETURNING id",
+                )
+                .bind(&input.name)
+                .bind(input.category)
+                .bind(position as i32)
+                .bind(input.wip_limit)
+                .bind(id)
+                .fetch_one(&mut *tx)
+                .await?
+            }
+            None => {
+                sqlx::query_as(
+                    r#"INSERT INTO workflow_states (project_id, name, category, position, wip_limit)
+                       VALUES ($1, $2, $3, $4, $5) RETURNING id"#,
+                )
+                .bind(project_id)
+                .bind(&input.name)
+                .bind(input.category)
+                .bind(position as i32)
+                .bind(input.wip_limit)
+                .fetch_one(&mut *tx)
+                .await?
+            }
+        };
+        ids.insert(input.name.clone(), id);
+    }
+
+    sqlx::query("DELETE FROM workflow_transitions WHERE project_id = $1")
+        .bind(project_id)
+        .execute(&mut *tx)
+        .await?;
+    for transition in body.transitions.iter().flatten() {
+        sqlx::query(
+            r#"INSERT INTO workflow_transitions (project_id, from_state_id, to_state_id) VALUES ($1, $2, $3)
+               ON CONFLICT DO NOTHING"#,
+        )
+        .bind(project_id)
+        .bind(ids[&transition.from])
+        .bind(ids[&transition.to])
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(Json(fetch_workflow(&state.db, project_id).await?))
+}
+
+// ======= ISSUES HANDLERS =======
+
+/// 任务查询列 (附带负责人、标签与子任务进度)，表别名必须为 i
+const ISSUE_COLUMNS: &str = r#"
+    i.*,
+    ARRAY(SELECT a.user_id FROM issue_assignees a WHERE a.issue_id = i.id ORDER BY a.user_id) AS assignee_ids,
+    ARRAY(SELECT il.label_id FROM issue_labels il WHERE il.issue_id = i.id ORDER BY il.label_id) AS label_ids,
+    (SELECT s.category FROM workflow_states s WHERE s.project_id = i.project_id AND s.name = i.status) AS status_category,
+    (SELECT COUNT(*) FROM issues c JOIN workflow_states cs ON cs.project_id = c.project_id AND cs.name = c.status
+     WHERE c.parent_id = i.id AND cs.category <> 'canceled') AS child_count,
+    (SELECT COUNT(*) FROM issues c JOIN workflow_states cs ON cs.project_id = c.project_id AND cs.name = c.status
+     WHERE c.parent_id = i.id AND cs.category = 'completed') AS completed_child_count,
+    (SELECT SUM(COALESCE(c.estimate, 1)) FILTER (WHERE cs.category = 'completed')::FLOAT8
+            / NULLIF(SUM(COALESCE(c.estimate, 1)), 0)
+     FROM issues c JOIN workflow_states cs ON cs.project_id = c.project_id AND cs.name = c.status
+     WHERE c.parent_id = i.id AND cs.category <> 'canceled') AS progress,
+    EXISTS (
+        SELECT 1 FROM issue_relations r
+        JOIN issues b ON b.id = r.source_issue_id
+        JOIN workflow_states bs ON bs.project_id = b.project_id AND bs.name = b.status
+        WHERE r.target_issue_id = i.id AND r.relation_type = 'blocks' AND bs.category NOT IN ('completed', 'canceled')
+    ) AS blocked"#;
+
+/// 任务同时带有参数 (小写标签名数组) 中所有标签的条件，参数为空时不筛选
+fn issue_has_labels_sql(param: &str) -> String {
+    format!(
+        r#"({param}::TEXT[] IS NULL OR (
+            SELECT COUNT(DISTINCT LOWER(l.name)) FROM issue_labels il JOIN labels l ON l.id = il.label_id
+            WHERE il.issue_id = i.id AND LOWER(l.name) = ANY({param})
+        ) = CARDINALITY({param}))"#
+    )
+}
+
+async fn fetch_issue(state: &AppState, issue_id: i32) -> Result<Issue, AppError> {
+    sqlx::query_as::<_, Issue>(&format!("SELECT {} FROM issues i WHERE i.id = $1", ISSUE_COLUMNS))
+        .bind(issue_id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("任务未找到".into()))
+}
+
+/// 在当前用户可见的项目中按条件查询任务
+async fn query_issues(
+    state: &AppState,
+    user: &AuthUser,
+    project_id: Option<i32>,
+    workspace_id: Option<i32>,
+    query: IssueQuery,
+    order_by: &str,
+) -> Result<Vec<Issue>, AppError> {
+    let (assignee_id, unassigned) = UserFilter::resolve(query.assignee, user.id);
+    let (creator_id, no_creator) = UserFilter::resolve(query.creator, user.id);
+    let label_names = query.label_names();
+    let order_by = query.sort.map_or(order_by, |sort| sort.order_by());
+    let due_range = match query.due {
+        Some(filter) => {
+            let (timezone, week_start_day): (String, i16) =
+                sqlx::query_as("SELECT timezone, week_start_day FROM users WHERE id = $1")
+                    .bind(user.id)
+                    .fetch_one(&state.db)
+                    .await?;
+            Some(calendar::due_range(filter, &timezone, week_start_day, Utc::now()))
+        }
+        None => None,
+    };
+    let (due_from, due_until) = due_range.map_or((None, None), |(from, until)| (from, Some(until)));
+    let issues = sqlx::query_as::<_, Issue>(&format!(
+        r#"SELECT {} FROM issues i
+           JOIN projects p ON p.id = i.project_id
+           WHERE i.project_id IN ({})
+             AND ($2::INTEGER IS NULL OR i.project_id = $2)
+             AND ($3::INTEGER IS NULL OR p.workspace_id = $3)
+             AND ($4::TEXT IS NULL OR i.title ILIKE $4 OR i.description ILIKE $4)
+             AND ($5::TEXT IS NULL OR i.status = $5)
+             AND ($6::INTEGER IS NULL OR EXISTS (SELECT 1 FROM issue_assignees a WHERE a.issue_id = i.id AND a.user_id = $6))
+             AND (NOT $7 OR NOT EXISTS (SELECT 1 FROM issue_assignees a WHERE a.issue_id = i.id))
+             AND ($8::INTEGER IS NULL OR i.creator_id = $8)
+             AND (NOT $9 OR i.creator_id IS NULL)
+             AND {}
+             AND ($11::TIMESTAMPTZ IS NULL OR i.due_date >= $11)
+             AND ($12::TIMESTAMPTZ IS NULL OR i.due_date < $12)
+             AND (NOT $13 OR NOT EXISTS (
+                 SELECT 1 FROM workflow_states s
+                 WHERE s.project_id = i.project_id AND s.name = i.status AND s.category IN ('completed', 'canceled')))
+           ORDER BY {}"#,
+        ISSUE_COLUMNS, VISIBLE_PROJECTS_SQL, issue_has_labels_sql("$10"), order_by
+    ))
+    .bind(user.id)
+    .bind(project_id)
+    .bind(workspace_id)
+    .bind(query.q.map(|s| format!("%{}%", s)))
+    .bind(query.status)
+    .bind(assignee_id)
+    .bind(unassigned)
+    .bind(creator_id)
+    .bind(no_creator)
+    .bind(label_names)
+    .bind(due_from)
+    .bind(due_until)
+    .bind(query.due == Some(DueFilter::Overdue))
+    .fetch_all(&state.db)
+    .await?;
+    Ok(issues)
+}
+
+/// 整体替换任务负责人；负责人必须能访问任务所属项目
+async fn set_issue_assignees(
+    state: &AppState,
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    issue_id: i32,
+    project_id: i32,
+    assignee_ids: &[i32],
+    assigned_by: i32,
+) -> Result<(), AppError> {
+    for &assignee_id in assignee_ids {
+        if project_role_of(&state.db, assignee_id, project_id).await?.is_none() {
+            return Err(AppError::BadRequest(format!("用户 {} 不是项目成员，不能指派", assignee_id)));
+        }
+    }
+    sqlx::query("DELETE FROM issue_assignees WHERE issue_id = $1 AND user_id <> ALL($2)")
+        .bind(issue_id)
+        .bind(assignee_ids)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query(
+        r#"INSERT INTO issue_assignees (issue_id, user_id, assigned_by)
+           SELECT $1, UNNEST($2::INTEGER[]), $3
+           ON CONFLICT (issue_id, user_id) DO NOTHING"#,
+    )
+    .bind(issue_id)
+    .bind(assignee_ids)
+    .bind(assigned_by)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// 整体替换任务标签；标签必须属于任务所在项目
+async fn set_issue_labels(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    issue_id: i32,
+    project_id: i32,
+    label_ids: &[i32],
+) -> Result<(), AppError> {
+    let (valid,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM labels WHERE project_id = $1 AND id = ANY($2)")
+        .bind(project_id)
+        .bind(label_ids)
+        .fetch_one(&mut **tx)
+        .await?;
+    let mut distinct = label_ids.to_vec();
+    distinct.sort_unstable();
+    distinct.dedup();
+    if valid as usize != distinct.len() {
+        return Err(AppError::BadRequest("标签不存在或不属于该项目".into()));
+    }
+
+    sqlx::query("DELETE FROM issue_labels WHERE issue_id = $1 AND label_id <> ALL($2)")
+        .bind(issue_id)
+        .bind(&distinct)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query(
+        r#"INSERT INTO issue_labels (issue_id, label_id) SELECT $1, UNNEST($2::INTEGER[])
+           ON CONFLICT (issue_id, label_id) DO NOTHING"#,
+    )
+    .bind(issue_id)
+    .bind(&distinct)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// 当前用户有权访问的所有项目中的任务
+pub async fn get_all_my_issues_handler(
+    user: AuthUser,
+    WorkspaceScope(workspace_id): WorkspaceScope,
+    Query(query): Query<IssueQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Issue>>, AppError> {
+    user.require_scope(Scope::IssuesRead)?;
+    let issues = query_issues(&state, &user, None, workspace_id, query, "i.updated_at DESC").await?;
+    Ok(Json(issues))
+}
+
+/// 指派给我的任务 (跨所有可访问的项目)
+pub async fn get_assigned_issues_handler(
+    user: AuthUser,
+    WorkspaceScope(workspace_id): WorkspaceScope,
+    Query(mut query): Query<IssueQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Issue>>, AppError> {
+    user.require_scope(Scope::IssuesRead)?;
+    query.assignee = Some(UserFilter::Me);
+    let issues = query_issues(&state, &user, None, workspace_id, query, "i.due_date ASC NULLS LAST, i.priority DESC").await?;
+    Ok(Json(issues))
+}
+
+pub async fn get_project_issues_handler(
+    user: AuthUser,
+    Path(project_id): Path<i32>,
+    Query(query): Query<IssueQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Issue>>, AppError> {
+    user.require_scope(Scope::IssuesRead)?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Viewer).await?;
+    let issues = query_issues(&state, &user, Some(project_id), None, query, "i.priority DESC, i.created_at DESC").await?;
+    Ok(Json(issues))
+}
+
+/// 校验父任务：必须属于同一项目，且不能是任务自身或其后代 (调用前需锁定项目行)
+async fn ensure_valid_parent(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    issue_id: Option<i32>,
+    parent_id: i32,
+    project_id: i32,
+) -> Result<(), AppError> {
+    let parent_project: Option<(i32,)> = sqlx::query_as("SELECT project_id FROM issues WHERE id = $1")
+        .bind(parent_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    if parent_project.map(|(p,)| p) != Some(project_id) {
+        return Err(AppError::BadRequest("父任务不存在或不属于同一项目".into()));
+    }
+
+    if let Some(issue_id) = issue_id {
+        let cycle = sqlx::query(
+            r#"WITH RECURSIVE ancestors AS (
+                   SELECT id, parent_id FROM issues WHERE id = $1
+                   UNION
+                   SELECT i.id, i.parent_id FROM issues i JOIN ancestors a ON i.id = a.parent_id
+               )
+               SELECT 1 FROM ancestors WHERE id = $2"#,
+        )
+        .bind(parent_id)
+        .bind(issue_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+        if cycle.is_some() {
+            return Err(AppError::BadRequest("不能将任务移动到自身或其子任务之下".into()));
+        }
+    }
+    Ok(())
+}
+
+/// 锁定项目行，串行化同一项目内的层级调整，避免并发调整产生环
+async fn lock_project(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, project_id: i32) -> Result<(), AppError> {
+    sqlx::query("SELECT id FROM projects WHERE id = $1 FOR UPDATE")
+        .bind(project_id)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+pub async fn create_issue_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<CreateIssueSchema>,
+) -> Result<Json<Issue>, AppError> {
+    user.require_scope(Scope::IssuesWrite)?;
+    user.require_verified_email(&state).await?;
+    Ok(Json(insert_issue(&state, &user, body).await?))
+}
+
+/// 在父任务下创建子任务
+pub async fn create_child_issue_handler(
+    user: AuthUser,
+    Path(parent_id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(mut body): ValidatedJson<CreateIssueSchema>,
+) -> Result<Json<Issue>, AppError> {
+    user.require_scope(Scope::IssuesWrite)?;
+    user.require_verified_email(&state).await?;
+    body.parent_id = Some(parent_id);
+    Ok(Json(insert_issue(&state, &user, body).await?))
+}
+
+async fn insert_issue(state: &AppState, user: &AuthUser, body: CreateIssueSchema) -> Result<Issue, AppError> {
+    // 指定父任务时项目取父任务所在项目
+    let project_id = match (body.parent_id, body.project_id) {
+        (Some(parent_id), project_id) => {
+            let (parent_project, _) = authorize_issue(&state.db, user, parent_id, ProjectRole::Editor).await?;
+            if project_id.is_some_and(|p| p != parent_project) {
+                return Err(AppError::BadRequest("父任务不属于指定项目".into()));
+            }
+            parent_project
+        }
+        (None, Some(project_id)) => {
+            authorize_project(&state.db, user, project_id, ProjectRole::Editor).await?;
+            project_id
+        }
+        (None, None) => return Err(AppError::BadRequest("请指定所属项目".into())),
+    };
+
+    let mut tx = state.db.begin().await?;
+    lock_project(&mut tx, project_id).await?;
+    if let Some(parent_id) = body.parent_id {
+        ensure_valid_parent(&mut tx, None, parent_id, project_id).await?;
+    }
+    if let Some(status) = &body.status {
+        workflow_state_category(&mut tx, project_id, status).await?;
+    }
+    // 新任务排在项目末尾
+    let (last_rank,): (Option<String>,) = sqlx::query_as("SELECT MAX(rank) FROM issues WHERE project_id = $1")
+        .bind(project_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    let rank = rank::between(last_rank.as_deref(), None).expect("没有上界时总能生成排序键");
+    // 未指定状态时取项目工作流的第一个状态
+    let (issue_id,): (i32,) = sqlx::query_as(
+        r#"INSERT INTO issues (project_id, creator_id, parent_id, title, description, priority, estimate, due_date, status, rank) 
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $8, COALESCE(
+               $9, (SELECT name FROM workflow_states WHERE project_id = $1 ORDER BY position LIMIT 1)), $10)
+           RETURNING id"#,
+    )
+    .bind(project_id)
+    .bind(user.id)
+    .bind(body.parent_id)
+    .bind(body.title)
+    .bind(body.description)
+    .bind(body.priority.unwrap_or(0))
+    .bind(body.estimate)
+    .bind(body.due_date)
+    .bind(body.status)
+    .bind(rank)
+    .fetch_one(&mut *tx)
+    .await?;
+    if let Some(assignee_ids) = &body.assignee_ids {
+        set_issue_assignees(state, &mut tx, issue_id, project_id, assignee_ids, user.id).await?;
+    }
+    if let Some(label_ids) = &body.label_ids {
+        set_issue_labels(&mut tx, issue_id, project_id, label_ids).await?;
+    }
+    tx.commit().await?;
+
+    fetch_issue(state, issue_id).await
+}
+
+/// 直接子任务
+pub async fn get_issue_children_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Issue>>, AppError> {
+    user.require_scope(Scope::IssuesRead)?;
+    authorize_issue(&state.db, &user, id, ProjectRole::Viewer).await?;
+    let children = sqlx::query_as::<_, Issue>(&format!(
+        "SELECT {} FROM issues i WHERE i.parent_id = $1 ORDER BY i.created_at",
+        ISSUE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(children))
+}
+
+/// 调整父任务 (同一项目内，不能成环)
+pub async fn set_issue_parent_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<SetParentSchema>,
+) -> Result<Json<Issue>, AppError> {
+    user.require_scope(Scope::IssuesWrite)?;
+    user.require_verified_email(&state).await?;
+    let (project_id, _) = authorize_issue(&state.db, &user, id, ProjectRole::Editor).await?;
+
+    let mut tx = state.db.begin().await?;
+    lock_project(&mut tx, project_id).await?;
+    if let Some(parent_id) = body.parent_id {
+        ensure_valid_parent(&mut tx, Some(id), parent_id, project_id).await?;
+    }
+    sqlx::query("UPDATE issues SET parent_id = $1, updated_at = NOW() WHERE id = $2")
+        .bind(body.parent_id)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(Json(fetch_issue(&state, id).await?))
+}
+
+/// 拖拽排序：在 after 与 before 之间生成新的排序键，可同时变更状态
+pub async fn move_issue_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    Query(query): Query<UpdateIssueQuery>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<MoveIssueSchema>,
+) -> Result<Json<Issue>, AppError> {
+    user.require_scope(Scope::IssuesWrite)?;
+    user.require_verified_email(&state).await?;
+    let (project_id, _) = authorize_issue(&state.db, &user, id, ProjectRole::Editor).await?;
+    if body.after == Some(id) || body.before == Some(id) {
+        return Err(AppError::BadRequest("不能以任务自身作为相邻任务".into()));
+    }
+
+    let mut tx = state.db.begin().await?;
+    lock_project(&mut tx, project_id).await?;
+    if let Some(status) = &body.status {
+        change_issue_status(&mut tx, id, project_id, status, query.force).await?;
+    }
+
+    // 只指定一侧时，另一侧取项目中紧邻的任务 (排除被移动的任务本身)
+    let (lower, upper) = match (body.after, body.before) {
+        (Some(after), Some(before)) => (
+            Some(neighbour_rank(&mut tx, project_id, after).await?),
+            Some(neighbour_rank(&mut tx, project_id, before).await?),
+        ),
+        (Some(after), None) => {
+            let lower = neighbour_rank(&mut tx, project_id, after).await?;
+            let (upper,): (Option<String>,) =
+                sqlx::query_as("SELECT MIN(rank) FROM issues WHERE project_id = $1 AND rank > $2 AND id <> $3")
+                    .bind(project_id)
+                    .bind(&lower)
+                    .bind(id)
+                    .fetch_one(&mut *tx)
+                    .await?;
+            (Some(lower), upper)
+        }
+        (None, Some(before)) => {
+            let upper = neighbour_rank(&mut tx, project_id, before).await?;
+            let (lower,): (Option<String>,) =
+                sqlx::query_as("SELECT MAX(rank) FROM issues WHERE project_id = $1 AND rank < $2 AND id <> $3")
+                    .bind(project_id)
+                    .bind(&upper)
+                    .bind(id)
+                    .fetch_one(&mut *tx)
+                    .await?;
+            (lower, Some(upper))
+        }
+        (None, None) => {
+            let (lower,): (Option<String>,) =
+                sqlx::query_as("SELECT MAX(rank) FROM issues WHERE project_id = $1 AND id <> $2")
+                    .bind(project_id)
+                    .bind(id)
+                    .fetch_one(&mut *tx)
+                    .await?;
+            (lower, None)
+        }
+    };
+    let rank = rank::between(lower.as_deref(), upper.as_deref())
+        .ok_or_else(|| AppError::BadRequest("after 指定的任务必须排在 before 之前".into()))?;
+
+    sqlx::query("UPDATE issues SET rank = $1, status = COALESCE($2, status), updated_at = NOW() WHERE id = $3")
+        .bind(rank)
+        .bind(body.status)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(Json(fetch_issue(&state, id).await?))
+}
+
+/// 相邻任务的排序键，必须属于同一项目
+async fn neighbour_rank(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    project_id: i32,
+    issue_id: i32,
+) -> Result<String, AppError> {
+    let rank: Option<(String,)> = sqlx::query_as("SELECT rank FROM issues WHERE id = $1 AND project_id = $2")
+        .bind(issue_id)
+        .bind(project_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    rank.map(|(r,)| r)
+        .ok_or_else(|| AppError::BadRequest(format!("相邻任务 #{} 不存在或不属于同一项目", issue_id)))
+}
+
+pub async fn update_issue_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    Query(query): Query<UpdateIssueQuery>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<UpdateIssueSchema>,
+) -> Result<Json<Issue>, AppError> {
+    user.require_scope(Scope::IssuesWrite)?;
+    user.require_verified_email(&state).await?;
+    let (project_id, _) = authorize_issue(&state.db, &user, id, ProjectRole::Editor).await?;
+
+    let mut tx = state.db.begin().await?;
+    if let Some(status) = &body.status {
+        change_issue_status(&mut tx, id, project_id, status, query.force).await?;
+    }
+    let res = sqlx::query(
+        r#"UPDATE issues SET 
+            title = COALESCE($1, title),
+            description = CASE WHEN $2 IS NULL THEN description ELSE $2 END,
+            status = COALESCE($3, status),
+            priority = COALESCE($4, priority),
+            due_date = COALESCE($5, due_date),
+            estimate = COALESCE($6, estimate),
+            updated_at = NOW()
+         WHERE id = $7"#,
+    )
+    .bind(body.title).bind(body.description).bind(body.status).bind(body.priority).bind(body.due_date)
+    .bind(body.estimate)
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+    if res.rows_affected() == 0 { return Err(AppError::NotFound("任务未找到".into())); }
+    if let Some(assignee_ids) = &body.assignee_ids {
+        set_issue_assignees(&state, &mut tx, id, project_id, assignee_ids, user.id).await?;
+    }
+    if let Some(label_ids) = &body.label_ids {
+        set_issue_labels(&mut tx, id, project_id, label_ids).await?;
+    }
+    tx.commit().await?;
+
+    Ok(Json(fetch_issue(&state, id).await?))
+}
+
+/// 检查状态变更：目标状态必须在项目工作流中，且符合流转规则
+/// 变为已完成分类时，任务仍被未完成任务阻塞则拒绝 (除非 force)
+/// 必须在事务中其他加锁操作之前调用 (或已持有 lock_project)
+async fn change_issue_status(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    issue_id: i32,
+    project_id: i32,
+    status: &str,
+    force: bool,
+) -> Result<(), AppError> {
+    // 与替换工作流 (lock_project 的 FOR UPDATE) 互斥，避免目标状态在检查之后被删除或改名；
+    // KEY SHARE 不阻塞同一项目中的其他状态变更与项目信息修改
+    sqlx::query("SELECT id FROM projects WHERE id = $1 FOR KEY SHARE")
+        .bind(project_id)
+        .execute(&mut **tx)
+        .await?;
+    let category = workflow_state_category(tx, project_id, status).await?;
+    let (current,): (String,) = sqlx::query_as("SELECT status FROM issues WHERE id = $1 FOR UPDATE")
+        .bind(issue_id)
+        .fetch_optional(&mut **tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound("任务未找到".into()))?;
+    if current == status {
+        return Ok(());
+    }
+
+    let (allowed,): (bool,) = sqlx::query_as(
+        r#"SELECT NOT EXISTS (SELECT 1 FROM workflow_transitions WHERE project_id = $1)
+               OR EXISTS (
+                   SELECT 1 FROM workflow_transitions t
+                   JOIN workflow_states f ON f.id = t.from_state_id
+                   JOIN workflow_states s ON s.id = t.to_state_id
+                   WHERE t.project_id = $1 AND f.name = $2 AND s.name = $3
+               )"#,
+    )
+    .bind(project_id)
+    .bind(&current)
+    .bind(status)
+    .fetch_one(&mut **tx)
+    .await?;
+    if !allowed {
+        return Err(AppError::Conflict(format!("工作流不允许从「{}」变更为「{}」", current, status)));
+    }
+
+    if category == StatusCategory::Completed && !force {
+        let blockers: Vec<(i32, String)> = sqlx::query_as(
+            r#"SELECT b.id, b.title FROM issue_relations r
+               JOIN issues b ON b.id = r.source_issue_id
+               JOIN workflow_states bs ON bs.project_id = b.project_id AND bs.name = b.status
+               WHERE r.target_issue_id = $1 AND r.relation_type = 'blocks' AND bs.category NOT IN ('completed', 'canceled')
+               ORDER BY b.id"#,
+        )
+        .bind(issue_id)
+        .fetch_all(&mut **tx)
+        .await?;
+        if !blockers.is_empty() {
+            let names: Vec<String> = blockers.iter().map(|(id, title)| format!("#{} {}", id, title)).collect();
+            return Err(AppError::Conflict(format!(
+                "任务仍被以下任务阻塞: {}；如需强制完成请添加 ?force=true",
+                names.join("、")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// 删除任务；有子任务时必须通过 ?children=cascade|promote 指定子任务的处理方式
+pub async fn delete_issue_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    Query(query): Query<DeleteIssueQuery>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    user.require_scope(Scope::IssuesWrite)?;
+    user.require_verified_email(&state).await?;
+    let (project_id, _) = authorize_issue(&state.db, &user, id, ProjectRole::Editor).await?;
+
+    let mut tx = state.db.begin().await?;
+    lock_project(&mut tx, project_id).await?;
+    let (parent_id,): (Option<i32>,) = sqlx::query_as("SELECT parent_id FROM issues WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound("任务未找到".into()))?;
+    let (child_count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM issues WHERE parent_id = $1")
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    match (child_count, query.children) {
+        (0, _) => {}
+        (_, None) => {
+            return Err(AppError::Conflict(format!(
+                "该任务有 {} 个子任务，请指定 children=cascade (一并删除) 或 children=promote (提升到上一级)",
+                child_count
+            )))
+        }
+        (_, Some(ChildrenOnDelete::Promote)) => {
+            sqlx::query("UPDATE issues SET parent_id = $1, updated_at = NOW() WHERE parent_id = $2")
+                .bind(parent_id)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        (_, Some(ChildrenOnDelete::Cascade)) => {
+            sqlx::query(
+                r#"WITH RECURSIVE descendants AS (
+                       SELECT id FROM issues WHERE parent_id = $1
+                       UNION
+                       SELECT i.id FROM issues i JOIN descendants d ON i.parent_id = d.id
+                   )
+                   DELETE FROM issues WHERE id IN (SELECT id FROM descendants)"#,
+            )
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    sqlx::query("DELETE FROM issues WHERE id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ======= ISSUE RELATION HANDLERS =======
+
+/// 从任务 $1 的视角查询关系
+const ISSUE_RELATION_SELECT: &str = r#"
+    SELECT r.id,
+           CASE
+               WHEN r.relation_type = 'blocks' AND r.source_issue_id = $1 THEN 'blocks'
+               WHEN r.relation_type = 'blocks' THEN 'blocked_by'
+               WHEN r.relation_type = 'duplicates' AND r.source_issue_id = $1 THEN 'duplicates'
+               WHEN r.relation_type = 'duplicates' THEN 'duplicated_by'
+               ELSE 'relates_to'
+           END::varchar AS kind,
+           o.id AS issue_id, o.title AS issue_title, o.status AS issue_status, r.created_at
+    FROM issue_relations r
+    JOIN issues o ON o.id = CASE WHEN r.source_issue_id = $1 THEN r.target_issue_id ELSE r.source_issue_id END
+    WHERE (r.source_issue_id = $1 OR r.target_issue_id = $1)"#;
+
+pub async fn get_issue_relations_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<IssueRelation>>, AppError> {
+    user.require_scope(Scope::IssuesRead)?;
+    authorize_issue(&state.db, &user, id, ProjectRole::Viewer).await?;
+    let relations = sqlx::query_as::<_, IssueRelation>(&format!("{} ORDER BY r.created_at", ISSUE_RELATION_SELECT))
+        .bind(id)
+        .fetch_all(&state.db)
+        .await?;
+    Ok(Json(relations))
+}
+
+/// 建立关系；阻塞关系不允许形成循环依赖
+pub async fn create_issue_relation_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<CreateRelationSchema>,
+) -> Result<Json<IssueRelation>, AppError> {
+    user.require_scope(Scope::IssuesWrite)?;
+    user.require_verified_email(&state).await?;
+    let (project_id, _) = authorize_issue(&state.db, &user, id, ProjectRole::Editor).await?;
+    let other = body.issue_id;
+    if other == id {
+        return Err(AppError::BadRequest("不能与任务自身建立关系".into()));
+    }
+    let other_project: Option<(i32,)> = sqlx::query_as("SELECT project_id FROM issues WHERE id = $1")
+        .bind(other)
+        .fetch_optional(&state.db)
+        .await?;
+    if other_project.map(|(p,)| p) != Some(project_id) {
+        return Err(AppError::BadRequest("关联任务不存在或不属于同一项目".into()));
+    }
+
+    // 统一存储方向: 被阻塞 / 被重复交换两端，相关关系按 ID 排序
+    let (source, target, relation_type) = match body.kind {
+        RelationKind::Blocks => (id, other, RelationType::Blocks),
+        RelationKind::BlockedBy => (other, id, RelationType::Blocks),
+        RelationKind::Duplicates => (id, other, RelationType::Duplicates),
+        RelationKind::DuplicatedBy => (other, id, RelationType::Duplicates),
+        RelationKind::RelatesTo => (id.min(other), id.max(other), RelationType::RelatesTo),
+    };
+
+    let mut tx = state.db.begin().await?;
+    lock_project(&mut tx, project_id).await?;
+    if relation_type == RelationType::Blocks {
+        // target 已经 (间接) 阻塞 source 时再加 source -> target 会成环
+        let cycle = sqlx::query(
+            r#"WITH RECURSIVE reachable AS (
+                   SELECT target_issue_id AS id FROM issue_relations WHERE source_issue_id = $1 AND relation_type = 'blocks'
+                   UNION
+                   SELECT r.target_issue_id FROM issue_relations r
+                   JOIN reachable ON r.source_issue_id = reachable.id
+                   WHERE r.relation_type = 'blocks'
+               )
+               SELECT 1 FROM reachable WHERE id = $2"#,
+        )
+        .bind(target)
+        .bind(source)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if cycle.is_some() {
+            return Err(AppError::BadRequest("该阻塞关系会形成循环依赖".into()));
+        }
+    }
+    let created: Option<(i32,)> = sqlx::query_as(
+        r#"INSERT INTO issue_relations (source_issue_id, target_issue_id, relation_type, created_by)
+           VALUES ($1, $2, $3, $4)
+           ON CONFLICT (source_issue_id, target_issue_id, relation_type) DO NOTHING
+           RETURNING id"#,
+    )
+    .bind(source)
+    .bind(target)
+    .bind(relation_type)
+    .bind(user.id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let (relation_id,) = created.ok_or_else(|| AppError::Conflict("关系已存在".into()))?;
+    tx.commit().await?;
+
+    let relation = sqlx::query_as::<_, IssueRelation>(&format!("{} AND r.id = $2", ISSUE_RELATION_SELECT))
+        .bind(id)
+        .bind(relation_id)
+        .fetch_one(&state.db)
+        .await?;
+    Ok(Json(relation))
+}
+
+pub async fn delete_issue_relation_handler(
+    user: AuthUser,
+    Path((id, relation_id)): Path<(i32, i32)>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    user.require_scope(Scope::IssuesWrite)?;
+    user.require_verified_email(&state).await?;
+    authorize_issue(&state.db, &user, id, ProjectRole::Editor).await?;
+    let res = sqlx::query("DELETE FROM issue_relations WHERE id = $1 AND (source_issue_id = $2 OR target_issue_id = $2)")
+        .bind(relation_id)
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound("关系不存在".into()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 项目依赖图：所有任务与关系，以及按阻塞关系计算的拓扑顺序与关键路径
+pub async fn get_project_graph_handler(
+    user: AuthUser,
+    Path(project_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<ProjectGraph>, AppError> {
+    user.require_scope(Scope::IssuesRead)?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Viewer).await?;
+
+    // 任务与关系在同一快照中读取，避免并发删除任务后出现悬空的边
+    let mut tx = state.db.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+    let issues = sqlx::query_as::<_, Issue>(&format!(
+        "SELECT {} FROM issues i WHERE i.project_id = $1 ORDER BY i.id",
+        ISSUE_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    let edges = sqlx::query_as::<_, IssueGraphEdge>(
+        r#"SELECT r.id, r.source_issue_id, r.target_issue_id, r.relation_type
+           FROM issue_relations r JOIN issues i ON i.id = r.source_issue_id
+           WHERE i.project_id = $1 ORDER BY r.id"#,
+    )
+    .bind(project_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let nodes: Vec<IssueGraphNode> = issues
+        .into_iter()
+        .map(|i| IssueGraphNode {
+            id: i.id,
+            title: i.title,
+            status: i.status,
+            status_category: i.status_category,
+            due_date: i.due_date,
+            blocked: i.blocked,
+        })
+        .collect();
+    let due_dates: HashMap<i32, Option<DateTime<Utc>>> = nodes.iter().map(|n| (n.id, n.due_date)).collect();
+    // 只保留两端都在图中的边
+    let edges: Vec<IssueGraphEdge> = edges
+        .into_iter()
+        .filter(|e| due_dates.contains_key(&e.source_issue_id) && due_dates.contains_key(&e.target_issue_id))
+        .collect();
+    let blocking: Vec<&IssueGraphEdge> = edges.iter().filter(|e| e.relation_type == RelationType::Blocks).collect();
+    let blocking_pairs: Vec<(i32, i32)> = blocking.iter().map(|e| (e.source_issue_id, e.target_issue_id)).collect();
+    let due_date_conflicts = blocking
+        .iter()
+        .filter(|e| match (due_dates.get(&e.source_issue_id), due_dates.get(&e.target_issue_id)) {
+            (Some(Some(blocker_due)), Some(Some(blocked_due))) => blocker_due > blocked_due,
+            _ => false,
+        })
+        .map(|e| e.id)
+        .collect();
+
+    Ok(Json(ProjectGraph {
+        topological_order: graph::topological_order(&nodes, &blocking_pairs),
+        critical_path: graph::critical_path(&nodes, &blocking_pairs),
+        due_date_conflicts,
+        nodes,
+        edges,
+    }))
+}
+
+// ======= BOARD HANDLERS =======
+
+/// 优先级 0-4 的列标题
+const PRIORITY_TITLES: [&str; 5] = ["无优先级", "低", "中", "高", "紧急"];
+
+fn board_column(key: String, title: String, issues: Vec<Issue>) -> BoardColumn {
+    BoardColumn {
+        key,
+        title,
+        color: None,
+        category: None,
+        wip_limit: None,
+        wip_exceeded: false,
+        count: issues.len(),
+        issues,
+    }
+}
+
+/// 项目看板：按状态 / 负责人 / 优先级 / 标签分列，列内默认按手动排序
+/// 筛选参数同任务列表；按状态分列时包含工作流中的所有状态 (即使为空)
+pub async fn get_project_board_handler(
+    user: AuthUser,
+    Path(project_id): Path<i32>,
+    Query(board): Query<BoardQuery>,
+    Query(query): Query<IssueQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Board>, AppError> {
+    user.require_scope(Scope::IssuesRead)?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Viewer).await?;
+    let issues = query_issues(&state, &user, Some(project_id), None, query, "i.rank, i.id").await?;
+    let total = issues.len();
+
+    let columns = match board.group_by {
+        BoardGroupBy::Status => status_board_columns(&state.db, project_id, issues).await?,
+        BoardGroupBy::Assignee => assignee_board_columns(&state.db, project_id, issues).await?,
+        BoardGroupBy::Priority => priority_board_columns(issues),
+        BoardGroupBy::Label => label_board_columns(&state.db, project_id, issues).await?,
+    };
+    Ok(Json(Board { group_by: board.group_by, total, columns }))
+}
+
+async fn status_board_columns(
+    db: &sqlx::PgPool,
+    project_id: i32,
+    issues: Vec<Issue>,
+) -> Result<Vec<BoardColumn>, AppError> {
+    let states = sqlx::query_as::<_, WorkflowState>(
+        "SELECT id, name, category, position, wip_limit, created_at FROM workflow_states WHERE project_id = $1 ORDER BY position",
+    )
+    .bind(project_id)
+    .fetch_all(db)
+    .await?;
+    // WIP 按该状态下的全部任务计算，不受筛选条件影响
+    let totals: HashMap<String, i64> =
+        sqlx::query_as::<_, (String, i64)>("SELECT status, COUNT(*) FROM issues WHERE project_id = $1 GROUP BY status")
+            .bind(project_id)
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .collect();
+
+    let mut by_status: HashMap<String, Vec<Issue>> = HashMap::new();
+    for issue in issues {
+        by_status.entry(issue.status.clone()).or_default().push(issue);
+    }
+    Ok(states
+        .into_iter()
+        .map(|s| {
+            let total = totals.get(&s.name).copied().unwrap_or(0);
+            let cards = by_status.remove(&s.name).unwrap_or_default();
+            BoardColumn {
+                category: Some(s.category),
+                wip_limit: s.wip_limit,
+                wip_exceeded: s.wip_limit.is_some_and(|limit| total > i64::from(limit)),
+                ..board_column(s.name.clone(), s.name, cards)
+            }
+        })
+        .collect())
+}
+
+/// 项目成员与出现在任务中的负责人各一列，最后是未指派
+async fn assignee_board_columns(
+    db: &sqlx::PgPool,
+    project_id: i32,
+    issues: Vec<Issue>,
+) -> Result<Vec<BoardColumn>, AppError> {
+    let assignee_ids: Vec<i32> = issues.iter().flat_map(|i| i.assignee_ids.iter().copied()).collect();
+    // 有权访问项目的用户 (含通过工作区获得权限的成员) 以及已被指派但已失去权限的用户
+    let users: Vec<(i32, String, Option<String>)> = sqlx::query_as(&format!(
+        r#"SELECT id, username, display_name FROM users
+           WHERE id IN ({}) OR id = ANY($2)
+           ORDER BY username"#,
+        PROJECT_USERS_SQL
+    ))
+    .bind(project_id)
+    .bind(&assignee_ids)
+    .fetch_all(db)
+    .await?;
+
+    let mut columns: Vec<BoardColumn> = users
+        .into_iter()
+        .map(|(id, username, display_name)| {
+            let cards = issues.iter().filter(|i| i.assignee_ids.contains(&id)).cloned().collect();
+            board_column(id.to_string(), display_name.unwrap_or(username), cards)
+        })
+        .collect();
+    let unassigned = issues.into_iter().filter(|i| i.assignee_ids.is_empty()).collect();
+    columns.push(board_column("none".into(), "未指派".into(), unassigned));
+    Ok(columns)
+}
+
+/// 优先级从高到低，0-4 始终有列
+fn priority_board_columns(issues: Vec<Issue>) -> Vec<BoardColumn> {
+    let mut priorities: Vec<i32> = (0..=4).chain(issues.iter().map(|i| i.priority)).collect();
+    priorities.sort_unstable_by(|a, b| b.cmp(a));
+    priorities.dedup();
+    priorities
+        .into_iter()
+        .map(|priority| {
+            let title = usize::try_from(priority)
+                .ok()
+                .and_then(|p| PRIORITY_TITLES.get(p))
+                .map_or_else(|| format!("P{}", priority), |t| t.to_string());
+            let cards = issues.iter().filter(|i| i.priority == priority).cloned().collect();
+            board_column(priority.to_string(), title, cards)
+        })
+        .collect()
+}
+
+/// 项目标签各一列，最后是无标签
+async fn label_board_columns(
+    db: &sqlx::PgPool,
+    project_id: i32,
+    issues: Vec<Issue>,
+) -> Result<Vec<BoardColumn>, AppError> {
+    let labels: Vec<(i32, String, String)> =
+        sqlx::query_as("SELECT id, name, color FROM labels WHERE project_id = $1 ORDER BY LOWER(name)")
+            .bind(project_id)
+            .fetch_all(db)
+            .await?;
+
+    let mut columns: Vec<BoardColumn> = labels
+        .into_iter()
+        .map(|(id, name, color)| {
+            let cards = issues.iter().filter(|i| i.label_ids.contains(&id)).cloned().collect();
+            BoardColumn { color: Some(color), ..board_column(id.to_string(), name, cards) }
+        })
+        .collect();
+    let unlabeled = issues.into_iter().filter(|i| i.label_ids.is_empty()).collect();
+    columns.push(board_column("none".into(), "无标签".into(), unlabeled));
+    Ok(columns)
+}
+
+// ======= LABEL HANDLERS =======
+
+const LABEL_SELECT: &str = r#"
+    SELECT l.*, (SELECT COUNT(*) FROM issue_labels il WHERE il.label_id = l.id) AS issue_count
+    FROM labels l"#;
+
+async fn fetch_label(state: &AppState, label_id: i32) -> Result<Label, AppError> {
+    sqlx::query_as::<_, Label>(&format!("{} WHERE l.id = $1", LABEL_SELECT))
+        .bind(label_id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("标签不存在或无权访问".into()))
+}
+
+/// 同一项目内标签名不区分大小写唯一
+async fn ensure_label_name_available(
+    state: &AppState,
+    project_id: i32,
+    name: &str,
+    except_id: Option<i32>,
+) -> Result<(), AppError> {
+    let taken = sqlx::query(
+        "SELECT id FROM labels WHERE project_id = $1 AND LOWER(name) = LOWER($2) AND ($3::INTEGER IS NULL OR id <> $3)",
+    )
+    .bind(project_id)
+    .bind(name)
+    .bind(except_id)
+    .fetch_optional(&state.db)
+    .await?;
+    if taken.is_some() {
+        return Err(AppError::Conflict("项目中已有同名标签".into()));
+    }
+    Ok(())
+}
+
+/// 并发创建或重命名时唯一索引兜底，同样按重名处理
+fn label_name_conflict(e: sqlx::Error) -> AppError {
+    match &e {
+        sqlx::Error::Database(db) if db.constraint() == Some("idx_labels_project_name") => {
+            AppError::Conflict("项目中已有同名标签".into())
+        }
+        _ => e.into(),
+    }
+}
+
+pub async fn get_project_labels_handler(
+    user: AuthUser,
+    Path(project_id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Label>>, AppError> {
+    user.require_scope(Scope::IssuesRead)?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Viewer).await?;
+    let labels = sqlx::query_as::<_, Label>(&format!("{} WHERE l.project_id = $1 ORDER BY LOWER(l.name)", LABEL_SELECT))
+        .bind(project_id)
+        .fetch_all(&state.db)
+        .await?;
+    Ok(Json(labels))
+}
+
+pub async fn create_label_handler(
+    user: AuthUser,
+    Path(project_id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<CreateLabelSchema>,
+) -> Result<Json<Label>, AppError> {
+    user.require_scope(Scope::IssuesWrite)?;
+    user.require_verified_email(&state).await?;
+    authorize_project(&state.db, &user, project_id, ProjectRole::Editor).await?;
+    let name = body.name.trim();
+    ensure_label_name_available(&state, project_id, name, None).await?;
+
+    let (label_id,): (i32,) = sqlx::query_as(
+        "INSERT INTO labels (project_id, name, color, description) VALUES ($1, $2, COALESCE($3, '#8B8D98'), $4) RETURNING id",
+    )
+    .bind(project_id)
+    .bind(name)
+    .bind(body.color)
+    .bind(body.description)
+    .fetch_one(&state.db)
+    .await
+    .map_err(label_name_conflict)?;
+
+    Ok(Json(fetch_label(&state, label_id).await?))
+}
+
+pub async fn update_label_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<UpdateLabelSchema>,
+) -> Result<Json<Label>, AppError> {
+    user.require_scope(Scope::IssuesWrite)?;
+    user.require_verified_email(&state).await?;
+    let (project_id, _) = authorize_label(&state.db, &user, id, ProjectRole::Editor).await?;
+    let name = body.name.as_deref().map(str::trim);
+    if let Some(name) = name {
+        ensure_label_name_available(&state, project_id, name, Some(id)).await?;
+    }
+
+    sqlx::query(
+        r#"UPDATE labels SET
+            name = COALESCE($1, name),
+            color = COALESCE($2, color),
+            description = CASE WHEN $3 THEN $4 ELSE description END
+         WHERE id = $5"#,
+    )
+    .bind(name)
+    .bind(body.color)
+    .bind(body.description.is_some())
+    .bind(body.description.flatten())
+    .bind(id)
+    .execute(&state.db)
+    .await
+    .map_err(label_name_conflict)?;
+
+    Ok(Json(fetch_label(&state, id).await?))
+}
+
+/// 删除标签 (同时从所有任务上移除)
+pub async fn delete_label_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    user.require_scope(Scope::IssuesWrite)?;
+    user.require_verified_email(&state).await?;
+    authorize_label(&state.db, &user, id, ProjectRole::Editor).await?;
+    sqlx::query("DELETE FROM labels WHERE id = $1")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ======= AUTH HANDLERS (无感刷新版本) =======
+
+pub async fn register_handler(
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<RegisterSchema>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if payload.email.is_none() && state.config.email_verification > EmailVerificationPolicy::Optional {
+        return Err(AppError::BadRequest("请填写邮箱".into()));
+    }
+    if let Some(email) = &payload.email {
+        let taken = sqlx::query("SELECT id FROM users WHERE LOWER(email) = LOWER($1)")
+            .bind(email)
+            .fetch_optional(&state.db)
+            .await?;
+        if taken.is_some() {
+            return Err(AppError::Conflict("邮箱已被其他账户使用".into()));
+        }
+    }
+
+    let hashed_password = hash_password(&payload.password, &state.config.argon2).map_err(|_| AppError::Internal)?;
+
+    // 邀请码与用户在同一事务中写入，注册失败时不消耗邀请码
+    let mut tx = state.db.begin().await?;
+    let invite_hash = invite_code_hash(payload.invite_code.as_deref());
+    let invited_by = admit_new_user(&state, &mut tx, invite_hash.as_deref()).await?;
+    let (user_id,): (i32,) = sqlx::query_as(
+        "INSERT INTO users (username, password_hash, email, invited_by) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(&payload.username).bind(hashed_password).bind(&payload.email).bind(invited_by)
+    .fetch_one(&mut *tx).await?;
+    create_personal_workspace(&mut tx, user_id, &payload.username).await?;
+    tx.commit().await?;
+
+    if let Some(email) = &payload.email {
+        send_verification_email(&state, user_id, &payload.username, email).await?;
+    }
+    Ok(Json(json!({"message": "User registered successfully"})))
+}
+
+/// 按注册策略判断是否允许创建新账户；邀请制下核销邀请码并返回邀请人
+async fn admit_new_user(
+    state: &AppState,
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    invite_code_hash: Option<&str>,
+) -> Result<Option<i32>, AppError> {
+    match state.config.registration {
+        RegistrationPolicy::Open => Ok(None),
+        RegistrationPolicy::Closed => Err(AppError::Forbidden("当前不开放注册".into())),
+        RegistrationPolicy::InviteOnly => {
+            let code_hash =
+                invite_code_hash.ok_or_else(|| AppError::Forbidden("当前仅限受邀注册，请填写邀请码".into()))?;
+            let redeemed: Option<(Option<i32>,)> = sqlx::query_as(
+                r#"UPDATE invite_codes SET use_count = use_count + 1
+                   WHERE code_hash = $1 AND revoked_at IS NULL AND expires_at > NOW() AND use_count < max_uses
+                   RETURNING created_by"#,
+            )
+            .bind(code_hash)
+            .fetch_optional(&mut **tx)
+            .await?;
+            redeemed
+                .map(|(created_by,)| created_by)
+                .ok_or_else(|| AppError::Forbidden("邀请码无效、已过期或已用完".into()))
+        }
+    }
+}
+
+/// 邀请码的哈希；去掉首尾空白，空字符串视为未填写
+fn invite_code_hash(code: Option<&str>) -> Option<String> {
+    code.map(str::trim).filter(|c| !c.is_empty()).map(hash_token)
+}
+
+/// 只检查邀请码当前是否可用，不核销 (第三方登录在跳转和换取令牌之前预检)
+async fn ensure_invite_usable(state: &AppState, code_hash: &str) -> Result<(), AppError> {
+    if state.config.registration != RegistrationPolicy::InviteOnly {
+        return Ok(());
+    }
+    let usable: Option<(i32,)> = sqlx::query_as(
+        r#"SELECT id FROM invite_codes
+           WHERE code_hash = $1 AND revoked_at IS NULL AND expires_at > NOW() AND use_count < max_uses"#,
+    )
+    .bind(code_hash)
+    .fetch_optional(&state.db)
+    .await?;
+    usable
+        .map(|_| ())
+        .ok_or_else(|| AppError::Forbidden("邀请码无效、已过期或已用完".into()))
+}
+
+pub async fn login_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    ValidatedJson(payload): ValidatedJson<LoginSchema>,
+) -> Result<Json<LoginResponse>, AppError> {
+    let user = authenticate_password(&state, &client, &payload).await?;
+    // 签发 Token (或进入两步验证)
+    Ok(Json(complete_login(&state, &user, &client).await?))
+}
+
+/// 校验用户名和密码 (登录与恢复账户共用)
+async fn authenticate_password(state: &AppState, client: &ClientInfo, payload: &LoginSchema) -> Result<User, AppError> {
+    // 1. 该用户名或来源 IP 失败次数过多时直接拒绝
+    let mut keys = vec![ThrottleKey::username(&payload.username)];
+    keys.extend(client.ip_address.clone().map(ThrottleKey::Ip));
+    state.throttle.check(&keys)?;
+
+    // 2. 验证用户 (用户不存在时也做一次哈希校验，保持耗时一致)
+    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(&payload.username).fetch_optional(&state.db).await?;
+    let password_hash = user
+        .as_ref()
+        .map(|u| u.password_hash.as_str())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| dummy_password_hash(&state.config.argon2));
+    let password_ok = verify_password(&payload.password, password_hash);
+
+    let user = match user {
+        Some(user) if password_ok && !user.password_hash.is_empty() => user,
+        _ => {
+            state.throttle.record_failure(&keys);
+            return Err(AppError::Auth("用户名或密码错误".into()));
+        }
+    };
+    state.throttle.record_success(&keys[0]);
+
+    // 3. 哈希参数低于当前配置时，趁持有明文密码透明地升级
+    if password_needs_rehash(&user.password_hash, &state.config.argon2) {
+        rehash_password(state, &user, &payload.password).await;
+    }
+    Ok(user)
+}
+
+/// 停用或已申请注销的账户不能登录
+fn ensure_account_active(user: &User) -> Result<(), AppError> {
+    if user.suspended_at.is_some() {
+        return Err(AppError::Forbidden("账户已停用".into()));
+    }
+    match (user.disabled_at, user.deletion_scheduled_at) {
+        (_, Some(scheduled_at)) => Err(AppError::Forbidden(format!(
+            "账户已申请注销，将于 {} 永久删除，在此之前可以恢复",
+            scheduled_at.format("%Y-%m-%d %H:%M UTC")
+        ))),
+        (Some(_), None) => Err(AppError::Forbidden("账户已停用".into())),
+        (None, None) => Ok(()),
+    }
+}
+
+/// 用当前参数重新哈希密码；失败只记录日志，不影响本次登录
+async fn rehash_password(state: &AppState, user: &User, password: &str) {
+    let new_hash = match hash_password(password, &state.config.argon2) {
+        Ok(hash) => hash,
+        Err(e) => {
+            tracing::warn!("重新哈希密码失败: user_id={}, {}", user.id, e);
+            return;
+        }
+    };
+    // 带上旧哈希作为条件，避免覆盖并发修改的新密码
+    let result = sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2 AND password_hash = $3")
+        .bind(&new_hash)
+        .bind(user.id)
+        .bind(&user.password_hash)
+        .execute(&state.db)
+        .await;
+    match result {
+        Ok(_) => tracing::info!("已升级密码哈希参数: user_id={}", user.id),
+        Err(e) => tracing::warn!("保存新密码哈希失败: user_id={}, {:?}", user.id, e),
+    }
+}
+
+/// 第一因素验证通过后：开启了两步验证的用户只签发中间态 Token，否则直接开启会话
+async fn complete_login(state: &AppState, user: &User, client: &ClientInfo) -> Result<LoginResponse, AppError> {
+    ensure_account_active(user)?;
+    ensure_email_allows_login(state, user)?;
+
+    if user.totp_enabled_at.is_some() {
+        return Ok(LoginResponse::MfaRequired(mfa_challenge(state, user, false)?));
+    }
+
+    Ok(LoginResponse::Authenticated(start_session(state, user, client).await?))
+}
+
+fn ensure_email_allows_login(state: &AppState, user: &User) -> Result<(), AppError> {
+    if state.config.email_verification == EmailVerificationPolicy::RequiredForLogin && user.email_verified_at.is_none() {
+        return Err(AppError::Forbidden("邮箱尚未验证，请先点击验证邮件中的链接".into()));
+    }
+    Ok(())
+}
+
+/// 签发两步验证的中间态 Token；restore 为 true 时第二步通过后才撤销注销申请
+fn mfa_challenge(state: &AppState, user: &User, restore: bool) -> Result<MfaChallenge, AppError> {
+    let mfa_token = create_mfa_token(&state.keys, user.id, user.token_version, restore)
+        .map_err(|_| AppError::Internal)?;
+    Ok(MfaChallenge {
+        mfa_required: true,
+        mfa_token,
+        expires_in: MFA_TOKEN_TTL_MINUTES * 60,
+    })
+}
+
+/// 两步登录第二步：用中间态 Token + 验证码 (或恢复码) 换取正式 Token
+pub async fn login_2fa_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    ValidatedJson(payload): ValidatedJson<MfaLoginSchema>,
+) -> Result<Json<AuthResponse>, AppError> {
+    let claims = verify_mfa_token(&state.keys, &payload.mfa_token)?;
+    state.revocation.check(&state.db, claims.sub, claims.ver, claims.jti).await?;
+
+    // 验证码只有 6 位，按用户限制尝试次数
+    let key = ThrottleKey::Mfa(claims.sub);
+    state.throttle.check(std::slice::from_ref(&key))?;
+
+    let mut tx = state.db.begin().await?;
+    if !verify_second_factor(&mut tx, claims.sub, &payload.code).await? {
+        state.throttle.record_failure(std::slice::from_ref(&key));
+        return Err(AppError::Auth("验证码错误".into()));
+    }
+    if claims.restore {
+        cancel_account_deletion(&mut tx, claims.sub).await?;
+    }
+    tx.commit().await?;
+    state.throttle.record_success(&key);
+
+    // 中间态 Token 只能使用一次
+    state.revocation.deny(&state.db, claims.sub, claims.jti, claims.exp).await?;
+
+    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+        .bind(claims.sub)
+        .fetch_one(&state.db)
+        .await?;
+    ensure_account_active(&user)?;
+    Ok(Json(start_session(&state, &user, &client).await?))
+}
+
+/// 为用户开启一个新的会话 (刷新令牌家族)，签发 Access Token 与 Refresh Token
+async fn start_session(state: &AppState, user: &User, client: &ClientInfo) -> Result<AuthResponse, AppError> {
+    let session_id = Uuid::new_v4();
+
+    // 1. 生成 Access Token (短效)
+    let token = create_jwt(&state.keys, user.id, &user.username, user.role, user.token_version, session_id)
+        .map_err(|_| AppError::Internal)?;
+
+    // 2. 生成并存储 Refresh Token (长效)
+    let refresh_token_str = issue_refresh_token(&state.db, user.id, session_id, client).await?;
+
+    Ok(AuthResponse { 
+        token, 
+        refresh_token: Some(refresh_token_str),
+        username: user.username.clone(),
+    })
+}
+
+/// 吊销用户的全部刷新令牌，并使已签发的 Access Token 失效
+/// 提交事务后需调用 state.revocation.invalidate，本实例才会立即拒绝旧 Token
+async fn revoke_all_sessions(
+    state: &AppState,
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: i32,
+) -> Result<i32, AppError> {
+    sqlx::query("UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+    state.revocation.bump_version(&mut **tx, user_id).await
+}
+
+/// 生成并存储一个属于指定家族的新刷新令牌
+async fn issue_refresh_token<'e, E>(
+    executor: E,
+    user_id: i32,
+    family_id: Uuid,
+    client: &ClientInfo,
+) -> Result<String, AppError>
+where
+    E: sqlx::PgExecutor<'e>,
+{
+    let refresh_token_str = generate_refresh_token();
+    let expires_at = Utc::now() + Duration::days(REFRESH_TOKEN_TTL_DAYS);
+
+    sqlx::query(
+        r#"INSERT INTO refresh_tokens (user_id, token, family_id, user_agent, ip_address, last_used_at, expires_at)
+           VALUES ($1, $2, $3, $4, $5, NOW(), $6)"#
+    )
+    .bind(user_id)
+    .bind(&refresh_token_str)
+    .bind(family_id)
+    .bind(&client.user_agent)
+    .bind(&client.ip_address)
+    .bind(expires_at)
+    .execute(executor)
+    .await?;
+
+    Ok(refresh_token_str)
+}
+
+#[derive(sqlx::FromRow)]
+struct RefreshTokenRow {
+    id: i32,
+    user_id: i32,
+    username: String,
+    role: Role,
+    token_version: i32,
+    disabled_at: Option<DateTime<Utc>>,
+    family_id: Uuid,
+    used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    expires_at: DateTime<Utc>,
+}
+
+// 核心：无感刷新接口 (滚动刷新 + 重放检测)
+pub async fn refresh_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Json(payload): Json<RefreshRequest>,
+) -> Result<Json<AuthResponse>, AppError> {
+    // 同一来源反复提交无效令牌时限流
+    let keys: Vec<ThrottleKey> = client.ip_address.clone().map(ThrottleKey::Ip).into_iter().collect();
+    state.throttle.check(&keys)?;
+
+    let mut tx = state.db.begin().await?;
+
+    // 1. 锁定该 Token 所在行，防止并发刷新同时通过检查
+    let row = sqlx::query_as::<_, RefreshTokenRow>(
+        r#"SELECT r.id, r.user_id, u.username, u.role, u.token_version, u.disabled_at, r.family_id, r.used_at, r.revoked_at, r.expires_at
+           FROM refresh_tokens r
+           JOIN users u ON r.user_id = u.id
+           WHERE r.token = $1
+           FOR UPDATE OF r"#,
+    )
+    .bind(&payload.refresh_token)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(row) = row else {
+        state.throttle.record_failure(&keys);
+        return Err(AppError::Auth("登录已过期，请重新登录".into()));
+    };
+
+    // 2. 已使用过的 Token 再次出现：说明令牌已泄露，吊销整个家族
+    if row.used_at.is_some() {
+        sqlx::query(
+            "UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND family_id = $2 AND revoked_at IS NULL",
+        )
+        .bind(row.user_id)
+        .bind(row.family_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        tracing::warn!(
+            "刷新令牌重放: user_id={}, family_id={}，已吊销该令牌家族",
+            row.user_id,
+            row.family_id
+        );
+        return Err(AppError::Auth(REFRESH_TOKEN_REUSED.into()));
+    }
+
+    if row.revoked_at.is_some() || row.expires_at <= Utc::now() {
+        return Err(AppError::Auth("登录已过期，请重新登录".into()));
+    }
+    if row.disabled_at.is_some() {
+        return Err(AppError::Auth("账户已停用".into()));
+    }
+
+    // 3. 标记旧 Token 已使用，并在同一家族中签发新 Token
+    sqlx::query("UPDATE refresh_tokens SET used_at = NOW() WHERE id = $1")
+        .bind(row.id)
+        .execute(&mut *tx)
+        .await?;
+    let new_refresh_token = issue_refresh_token(&mut *tx, row.user_id, row.family_id, &client).await?;
+    tx.commit().await?;
+
+    // 4. 签发新的 Access Token
+    let new_access_token =
+        create_jwt(&state.keys, row.user_id, &row.username, row.role, row.token_version, row.family_id)
+            .map_err(|_| AppError::Internal)?;
+
+    Ok(Json(AuthResponse {
+        token: new_access_token,
+        refresh_token: Some(new_refresh_token),
+        username: row.username,
+    }))
+}
+
+/// 公开 JWT 验签公钥 (JWK Set)
+pub async fn jwks_handler(
+    State(state): State<AppState>,
+) -> ([(header::HeaderName, &'static str); 1], Json<serde_json::Value>) {
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(state.keys.jwks()),
+    )
+}
+
+// ======= EMAIL VERIFICATION HANDLERS =======
+
+/// 两次发送验证邮件的最小间隔 (秒)
+const EMAIL_VERIFICATION_RESEND_SECONDS: i64 = 60;
+
+/// 签发验证链接并在后台发送到指定邮箱
+async fn send_verification_email(state: &AppState, user_id: i32, username: &str, email: &str) -> Result<(), AppError> {
+    let token = create_email_verification_token(&state.keys, user_id, email).map_err(|_| AppError::Internal)?;
+    sqlx::query("UPDATE users SET email_verification_sent_at = NOW() WHERE id = $1")
+        .bind(user_id)
+        .execute(&state.db)
+        .await?;
+
+    let message = Email {
+        to: email.to_string(),
+        subject: "验证你的邮箱".into(),
+        body: format!(
+            "{}，你好：\n\n请在 {} 小时内打开以下链接验证邮箱：\n{}/verify-email?token={}\n\n如果这不是你本人的操作，请忽略此邮件。",
+            username, EMAIL_VERIFICATION_TTL_HOURS, state.config.app_base_url, token
+        ),
+    };
+    let mailer = state.mailer.clone();
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(message).await {
+            tracing::error!("发送验证邮件失败: {}", e);
+        }
+    });
+    Ok(())
+}
+
+/// 打开邮件中的链接后由前端提交令牌；令牌中的邮箱必须仍是账户当前的邮箱
+pub async fn verify_email_handler(
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<VerifyEmailSchema>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let claims = verify_email_verification_token(&state.keys, &body.token)?;
+
+    let res = sqlx::query(
+        "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()) WHERE id = $1 AND email = $2",
+    )
+    .bind(claims.sub)
+    .bind(&claims.email)
+    .execute(&state.db)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::BadRequest("验证链接无效或已过期".into()));
+    }
+
+    Ok(Json(json!({"message": "邮箱验证成功"})))
+}
+
+/// 重新发送验证邮件 (无需登录，未验证前可能无法登录)；无论邮箱是否存在都返回相同结果
+pub async fn resend_verification_handler(
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<ResendVerificationSchema>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user = sqlx::query_as::<_, User>(
+        r#"SELECT * FROM users
+           WHERE LOWER(email) = LOWER($1) AND email_verified_at IS NULL
+             AND (email_verification_sent_at IS NULL
+                  OR email_verification_sent_at < NOW() - make_interval(secs => $2))"#,
+    )
+    .bind(&body.email)
+    .bind(EMAIL_VERIFICATION_RESEND_SECONDS as f64)
+    .fetch_optional(&state.db)
+    .await?;
+
+    if let Some(user) = user {
+        if let Some(email) = &user.email {
+            send_verification_email(&state, user.id, &user.username, email).await?;
+        }
+    }
+
+    Ok(Json(json!({"message": "如果该邮箱已注册且尚未验证，你将收到一封验证邮件"})))
+}
+
+// ======= PROFILE HANDLERS =======
+
+pub async fn get_me_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<User>, AppError> {
+    user.require_session()?;
+    let account = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("用户不存在".into()))?;
+    Ok(Json(account))
+}
+
+/// 修改个人资料；用户名与邮箱不区分大小写地检查唯一性
+pub async fn update_me_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<UpdateProfileSchema>,
+) -> Result<Json<User>, AppError> {
+    user.require_session()?;
+
+    if let Some(username) = &body.username {
+        let taken = sqlx::query("SELECT id FROM users WHERE LOWER(username) = LOWER($1) AND id <> $2")
+            .bind(username)
+            .bind(user.id)
+            .fetch_optional(&state.db)
+            .await?;
+        if taken.is_some() {
+            return Err(AppError::Conflict("用户名已被占用".into()));
+        }
+    }
+
+    let (old_email,): (Option<String>,) = sqlx::query_as("SELECT email FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_one(&state.db)
+        .await?;
+    let new_email = body.email.clone().flatten();
+    let email_changed = body.email.is_some() && new_email != old_email;
+
+    if let Some(Some(email)) = &body.email {
+        let taken = sqlx::query("SELECT id FROM users WHERE LOWER(email) = LOWER($1) AND id <> $2")
+            .bind(email)
+            .bind(user.id)
+            .fetch_optional(&state.db)
+            .await?;
+        if taken.is_some() {
+            return Err(AppError::Conflict("邮箱已被其他账户使用".into()));
+        }
+    }
+
+    if let Some(Some(project_id)) = body.default_project_id {
+        authorize_project(&state.db, &user, project_id, ProjectRole::Viewer)
+            .await
+            .map_err(|_| AppError::BadRequest("默认项目不存在或无权访问".into()))?;
+    }
+
+    let account = sqlx::query_as::<_, User>(
+        r#"UPDATE users SET
+            username = COALESCE($1, username),
+            display_name = CASE WHEN $2 THEN $3 ELSE display_name END,
+            email = CASE WHEN $4 THEN $5 ELSE email END,
+            email_verified_at = CASE WHEN $14 THEN NULL ELSE email_verified_at END,
+            avatar_url = CASE WHEN $6 THEN $7 ELSE avatar_url END,
+            timezone = COALESCE($8, timezone),
+            locale = COALESCE($9, locale),
+            week_start_day = COALESCE($10, week_start_day),
+            default_project_id = CASE WHEN $11 THEN $12 ELSE default_project_id END
+         WHERE id = $13
+         RETURNING *"#,
+    )
+    .bind(body.username)
+    .bind(body.display_name.is_some()).bind(body.display_name.flatten())
+    .bind(body.email.is_some()).bind(body.email.flatten())
+    .bind(body.avatar_url.is_some()).bind(body.avatar_url.flatten())
+    .bind(body.timezone)
+    .bind(body.locale)
+    .bind(body.week_start_day)
+    .bind(body.default_project_id.is_some()).bind(body.default_project_id.flatten())
+    .bind(user.id)
+    .bind(email_changed)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| AppError::NotFound("用户不存在".into()))?;
+
+    // 新邮箱需要重新验证
+    if let (true, Some(email)) = (email_changed, &account.email) {
+        send_verification_email(&state, account.id, &account.username, email).await?;
+    }
+
+    Ok(Json(account))
+}
+
+// ======= ACCOUNT HANDLERS =======
+
+/// 导出当前用户的全部个人数据 (每类数据一个 JSON 数组)，不包含密码哈希、两步验证密钥等凭据
+pub async fn export_me_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<([(header::HeaderName, String); 1], Json<AccountExport>), AppError> {
+    user.require_session()?;
+
+    let account = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("用户不存在".into()))?;
+    let workspaces = sqlx::query_as::<_, MyWorkspace>(
+        r#"SELECT w.*, m.role FROM workspaces w
+           JOIN workspace_members m ON m.workspace_id = w.id AND m.user_id = $1
+           ORDER BY w.id"#,
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await?;
+    let project_memberships = sqlx::query_as::<_, ProjectMembership>(
+        r#"SELECT m.project_id, p.name AS project_name, p.workspace_id, m.role, m.created_at
+           FROM project_members m JOIN projects p ON p.id = m.project_id
+           WHERE m.user_id = $1 ORDER BY m.project_id"#,
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await?;
+    let projects = sqlx::query_as::<_, Project>("SELECT * FROM projects WHERE user_id = $1 ORDER BY id")
+        .bind(user.id)
+        .fetch_all(&state.db)
+        .await?;
+    let member_projects = sqlx::query_as::<_, Project>(&format!(
+        r#"SELECT p.* FROM projects p
+           WHERE p.id IN ({}) AND p.user_id IS DISTINCT FROM $1
+           ORDER BY p.id"#,
+        VISIBLE_PROJECTS_SQL
+    ))
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await?;
+    let transfers = sqlx::query_as::<_, ProjectTransfer>(&format!(
+        "{} WHERE t.from_user_id = $1 OR t.to_user_id = $1 OR t.requested_by = $1 ORDER BY t.id",
+        PROJECT_TRANSFER_SELECT
+    ))
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await?;
+    let issues = sqlx::query_as::<_, Issue>(&format!(
+        r#"SELECT {} FROM issues i
+           WHERE i.creator_id = $1 OR EXISTS (SELECT 1 FROM issue_assignees a WHERE a.issue_id = i.id AND a.user_id = $1)
+           ORDER BY i.id"#,
+        ISSUE_COLUMNS
+    ))
+        .bind(user.id)
+        .fetch_all(&state.db)
+        .await?;
+    let comments = sqlx::query_as::<_, Comment>(
+        r#"SELECT c.id, c.issue_id, c.user_id, u.username, c.content, c.created_at
+           FROM comments c JOIN users u ON c.user_id = u.id
+           WHERE c.user_id = $1 ORDER BY c.id"#,
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await?;
+    // 每个会话 (刷新令牌家族) 取最新一条记录
+    let sessions = sqlx::query_as::<_, SessionRecord>(
+        r#"SELECT DISTINCT ON (family_id) family_id AS id,
+                  MIN(created_at) OVER (PARTITION BY family_id) AS created_at,
+                  last_used_at, user_agent, ip_address, expires_at, revoked_at
+           FROM refresh_tokens WHERE user_id = $1
+           ORDER BY family_id, created_at DESC"#,
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await?;
+    let identities = sqlx::query_as::<_, UserIdentity>(
+        r#"SELECT id, issuer, subject, email, created_at, last_login_at
+           FROM user_identities WHERE user_id = $1 ORDER BY created_at"#,
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await?;
+    let personal_access_tokens = sqlx::query_as::<_, PersonalAccessToken>(
+        r#"SELECT id, name, token_prefix, scopes, expires_at, last_used_at, created_at
+           FROM personal_access_tokens WHERE user_id = $1 ORDER BY created_at"#,
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await?;
+
+    let disposition = format!(
+        "attachment; filename=\"my-life-planner-{}-{}.json\"",
+        account.id,
+        Utc::now().format("%Y%m%d")
+    );
+    Ok((
+        [(header::CONTENT_DISPOSITION, disposition)],
+        Json(AccountExport {
+            exported_at: Utc::now(),
+            user: account,
+            workspaces,
+            project_memberships,
+            projects,
+            member_projects,
+            transfers,
+            issues,
+            comments,
+            sessions,
+            identities,
+            personal_access_tokens,
+        }),
+    ))
+}
+
+/// 注销账户：校验密码后立即停用并下线所有设备，宽限期结束后由后台任务永久删除
+/// 宽限期配置为 0 时立即删除
+pub async fn delete_me_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<DeleteAccountSchema>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    user.require_session()?;
+    let account = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("用户不存在".into()))?;
+
+    if account.password_hash.is_empty() {
+        return Err(AppError::BadRequest("账户尚未设置密码，请先通过找回密码设置密码后再注销".into()));
+    }
+    if !verify_password(&body.password, &account.password_hash) {
+        return Err(AppError::Auth("密码错误".into()));
+    }
+
+    let grace_days = state.config.account_deletion_grace_days;
+    if grace_days > 0 {
+        // 宽限期内可以撤销注销，所有权在真正删除时才交接；这里只预检是否会被拒绝
+        let mut check = state.db.begin().await?;
+        release_account_ownership(&mut check, user.id).await?;
+        check.rollback().await?;
+    }
+
+    let mut tx = state.db.begin().await?;
+    revoke_all_sessions(&state, &mut tx, user.id).await?;
+
+    if grace_days == 0 {
+        release_account_ownership(&mut tx, user.id).await?;
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(user.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        state.revocation.invalidate(user.id);
+        tracing::info!("账户已删除: user_id={}", user.id);
+        return Ok(Json(json!({"message": "账户已删除"})));
+    }
+
+    let (scheduled_at,): (DateTime<Utc>,) = sqlx::query_as(
+        r#"UPDATE users SET disabled_at = NOW(), deletion_scheduled_at = NOW() + make_interval(days => $2)
+           WHERE id = $1 RETURNING deletion_scheduled_at"#,
+    )
+    .bind(user.id)
+    .bind(grace_days as i32)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    state.revocation.invalidate(user.id);
+    tracing::info!("账户申请注销: user_id={}, 将于 {} 删除", user.id, scheduled_at);
+
+    Ok(Json(json!({
+        "message": "账户已停用，宽限期结束后将永久删除",
+        "deletion_scheduled_at": scheduled_at,
+    })))
+}
+
+/// 撤销注销申请并重新启用账户；被管理员停用的账户不能自行恢复
+async fn cancel_account_deletion(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, user_id: i32) -> Result<(), AppError> {
+    let res = sqlx::query(
+        r#"UPDATE users SET disabled_at = NULL, deletion_scheduled_at = NULL
+           WHERE id = $1 AND suspended_at IS NULL AND deletion_scheduled_at IS NOT NULL"#,
+    )
+    .bind(user_id)
+    .execute(&mut **tx)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::Forbidden("账户已被管理员停用或没有待处理的注销申请".into()));
+    }
+    tracing::info!("账户已恢复: user_id={}", user_id);
+    Ok(())
+}
+
+/// 宽限期内用用户名和密码撤销注销申请，成功后直接登录
+/// 开启了两步验证的账户需再通过 /api/login/2fa，验证码正确后才撤销
+pub async fn restore_account_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    ValidatedJson(payload): ValidatedJson<LoginSchema>,
+) -> Result<Json<LoginResponse>, AppError> {
+    let mut user = authenticate_password(&state, &client, &payload).await?;
+    if user.suspended_at.is_some() {
+        return Err(AppError::Forbidden("账户已被管理员停用，无法自行恢复".into()));
+    }
+    if user.deletion_scheduled_at.is_none() {
+        return Err(AppError::BadRequest("该账户没有待处理的注销申请".into()));
+    }
+
+    // 开启了两步验证时只凭密码不能撤销，验证码通过后在 login_2fa_handler 中恢复
+    if user.totp_enabled_at.is_some() {
+        ensure_email_allows_login(&state, &user)?;
+        return Ok(Json(LoginResponse::MfaRequired(mfa_challenge(&state, &user, true)?)));
+    }
+
+    let mut tx = state.db.begin().await?;
+    cancel_account_deletion(&mut tx, user.id).await?;
+    tx.commit().await?;
+    user.disabled_at = None;
+    user.deletion_scheduled_at = None;
+
+    Ok(Json(complete_login(&state, &user, &client).await?))
+}
+
+// ======= PASSWORD HANDLERS =======
+
+/// 密码重置令牌有效期 (分钟)
+const PASSWORD_RESET_TTL_MINUTES: i64 = 30;
+
+/// 修改密码：校验旧密码，吊销所有会话，并为当前设备重新签发 Token
+pub async fn change_password_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+    client: ClientInfo,
+    ValidatedJson(body): ValidatedJson<ChangePasswordSchema>,
+) -> Result<Json<AuthResponse>, AppError> {
+    user.require_session()?;
+    let mut account = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("用户不存在".into()))?;
+
+    if !verify_password(&body.old_password, &account.password_hash) {
+        return Err(AppError::BadRequest("原密码错误".into()));
+    }
+
+    let hashed_password = hash_password(&body.new_password, &state.config.argon2).map_err(|_| AppError::Internal)?;
+
+    let mut tx = state.db.begin().await?;
+    sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+        .bind(hashed_password)
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+    account.token_version = revoke_all_sessions(&state, &mut tx, user.id).await?;
+    tx.commit().await?;
+    state.revocation.invalidate(user.id);
+
+    Ok(Json(start_session(&state, &account, &client).await?))
+}
+
+/// 申请重置密码：无论邮箱是否存在都返回相同结果，避免泄露注册信息
+pub async fn forgot_password_handler(
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<ForgotPasswordSchema>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE LOWER(email) = LOWER($1)")
+        .bind(&body.email)
+        .fetch_optional(&state.db)
+        .await?;
+
+    if let Some(user) = user {
+        let token = generate_secure_token();
+        let expires_at = Utc::now() + Duration::minutes(PASSWORD_RESET_TTL_MINUTES);
+
+        // 新令牌签发后，之前未使用的令牌全部作废
+        let mut tx = state.db.begin().await?;
+        sqlx::query("UPDATE password_reset_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL")
+            .bind(user.id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)")
+            .bind(user.id)
+            .bind(hash_token(&token))
+            .bind(expires_at)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        let email = Email {
+            to: user.email.clone().unwrap_or(body.email),
+            subject: "重置密码".into(),
+            body: format!(
+                "{}，你好：\n\n请在 {} 分钟内打开以下链接重置密码：\n{}/reset-password?token={}\n\n如果这不是你本人的操作，请忽略此邮件。",
+                user.username, PASSWORD_RESET_TTL_MINUTES, state.config.app_base_url, token
+            ),
+        };
+
+        // 后台发送，响应时间不随邮箱是否存在而变化
+        let mailer = state.mailer.clone();
+        tokio::spawn(async move {
+            if let Err(e) = mailer.send(email).await {
+                tracing::error!("发送重置密码邮件失败: {}", e);
+            }
+        });
+    }
+
+    Ok(Json(json!({"message": "如果该邮箱已注册，你将收到一封重置密码邮件"})))
+}
+
+/// 使用邮件中的令牌重置密码，令牌单次有效；成功后吊销所有会话
+pub async fn reset_password_handler(
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<ResetPasswordSchema>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let mut tx = state.db.begin().await?;
+
+    let (token_id, user_id): (i32, i32) = sqlx::query_as(
+        r#"SELECT id, user_id FROM password_reset_tokens
+           WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+           FOR UPDATE"#,
+    )
+    .bind(hash_token(&body.token))
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("重置链接无效或已过期".into()))?;
+
+    let hashed_password = hash_password(&body.new_password, &state.config.argon2).map_err(|_| AppError::Internal)?;
+
+    sqlx::query("UPDATE password_reset_tokens SET used_at = NOW() WHERE id = $1")
+        .bind(token_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+        .bind(hashed_password)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    revoke_all_sessions(&state, &mut tx, user_id).await?;
+    tx.commit().await?;
+    state.revocation.invalidate(user_id);
+
+    Ok(Json(json!({"message": "密码已重置，请重新登录"})))
+}
+
+// ======= OIDC HANDLERS =======
+
+/// 授权请求 (state / nonce / PKCE) 的有效期 (分钟)
+const OIDC_STATE_TTL_MINUTES: i64 = 10;
+
+fn oidc_client(state: &AppState) -> Result<&OidcClient, AppError> {
+    state.oidc.as_deref().ok_or_else(|| AppError::NotFound("未启用第三方登录".into()))
+}
+
+/// 生成授权地址，并保存回调时需要的 state、nonce、PKCE verifier 与邀请码哈希
+async fn begin_oidc(
+    state: &AppState,
+    link_user_id: Option<i32>,
+    invite_code_hash: Option<String>,
+) -> Result<OidcAuthorizeResponse, AppError> {
+    let client = oidc_client(state)?;
+    let request = client.authorization_request().await.map_err(|e| {
+        tracing::error!("OIDC 授权请求失败: {}", e);
+        AppError::Internal
+    })?;
+
+    sqlx::query("DELETE FROM oidc_login_states WHERE expires_at < NOW()")
+        .execute(&state.db)
+        .await?;
+    sqlx::query(
+        r#"INSERT INTO oidc_login_states (state, nonce, code_verifier, link_user_id, invite_code_hash, expires_at)
+           VALUES ($1, $2, $3, $4, $5, $6)"#,
+    )
+    .bind(hash_token(&request.state))
+    .bind(&request.nonce)
+    .bind(&request.code_verifier)
+    .bind(link_user_id)
+    .bind(invite_code_hash)
+    .bind(Utc::now() + Duration::minutes(OIDC_STATE_TTL_MINUTES))
+    .execute(&state.db)
+    .await?;
+
+    Ok(OidcAuthorizeResponse { authorization_url: request.url })
+}
+
+/// 第三方登录第一步：前端跳转到返回的授权地址
+/// 仅限受邀注册时，新用户在这里带上邀请码，跳转前先检查是否可用
+pub async fn oidc_authorize_handler(
+    State(state): State<AppState>,
+    Query(query): Query<OidcAuthorizeQuery>,
+) -> Result<Json<OidcAuthorizeResponse>, AppError> {
+    let invite_hash = invite_code_hash(query.invite_code.as_deref());
+    if let Some(hash) = &invite_hash {
+        ensure_invite_usable(&state, hash).await?;
+    }
+    Ok(Json(begin_oidc(&state, None, invite_hash).await?))
+}
+
+/// 已登录用户关联第三方身份，回调流程与登录相同
+pub async fn link_oidc_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<OidcAuthorizeResponse>, AppError> {
+    user.require_session()?;
+    Ok(Json(begin_oidc(&state, Some(user.id), None).await?))
+}
+
+#[derive(sqlx::FromRow)]
+struct OidcStateRow {
+    nonce: String,
+    code_verifier: String,
+    link_user_id: Option<i32>,
+    invite_code_hash: Option<String>,
+}
+
+/// 第三方登录第二步：前端把回调地址上的 code 与 state 提交过来
+/// 已关联的身份直接登录；关联流程绑定到发起关联的用户；否则创建新用户
+pub async fn oidc_callback_handler(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    ValidatedJson(body): ValidatedJson<OidcCallbackSchema>,
+) -> Result<Json<LoginResponse>, AppError> {
+    let oidc = oidc_client(&state)?;
+
+    let state_hash = hash_token(&body.state);
+    let invalid_state = || AppError::BadRequest("登录请求无效或已过期，请重试".into());
+
+    // 1. 先检查邀请码，不可用时 state 与 code 都保留，前端换个邀请码还能重试
+    let pending = sqlx::query_as::<_, OidcStateRow>(
+        r#"SELECT nonce, code_verifier, link_user_id, invite_code_hash
+           FROM oidc_login_states WHERE state = $1 AND expires_at > NOW()"#,
+    )
+    .bind(&state_hash)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(invalid_state)?;
+    let invite_hash = invite_code_hash(body.invite_code.as_deref()).or(pending.invite_code_hash);
+    if let (Some(hash), None) = (&invite_hash, pending.link_user_id) {
+        ensure_invite_usable(&state, hash).await?;
+    }
+
+    // 2. state 单次有效
+    let pending = sqlx::query_as::<_, OidcStateRow>(
+        r#"DELETE FROM oidc_login_states WHERE state = $1 AND expires_at > NOW()
+           RETURNING nonce, code_verifier, link_user_id, invite_code_hash"#,
+    )
+    .bind(&state_hash)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(invalid_state)?;
+
+    // 3. 换取并校验 ID Token
+    let claims = oidc
+        .exchange_code(&body.code, &pending.code_verifier, &pending.nonce)
+        .await
+        .map_err(|e| {
+            tracing::warn!("OIDC 回调失败: {}", e);
+            AppError::Auth("第三方登录失败".into())
+        })?;
+
+    // 4. 找到或创建对应的本地用户
+    let mut tx = state.db.begin().await?;
+    let linked: Option<(i32,)> =
+        sqlx::query_as("SELECT user_id FROM user_identities WHERE issuer = $1 AND subject = $2")
+            .bind(oidc.issuer())
+            .bind(&claims.sub)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+    let user_id = match (linked, pending.link_user_id) {
+        (Some((owner,)), Some(link_user_id)) if owner != link_user_id => {
+            return Err(AppError::BadRequest("该第三方账号已关联其他用户".into()));
+        }
+        (Some((owner,)), _) => owner,
+        (None, Some(link_user_id)) => link_user_id,
+        (None, None) => {
+            let invited_by = admit_new_user(&state, &mut tx, invite_hash.as_deref()).await?;
+            create_oidc_user(&mut tx, &claims, invited_by).await?
+        }
+    };
+
+    sqlx::query(
+        r#"INSERT INTO user_identities (user_id, issuer, subject, email, last_login_at)
+           VALUES ($1, $2, $3, $4, NOW())
+           ON CONFLICT (issuer, subject) DO UPDATE SET email = EXCLUDED.email, last_login_at = NOW()"#,
+    )
+    .bind(user_id)
+    .bind(oidc.issuer())
+    .bind(&claims.sub)
+    .bind(&claims.email)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(&state.db)
+        .await?;
+    Ok(Json(complete_login(&state, &user, &client).await?))
+}
+
+/// 为首次登录的第三方身份创建本地用户 (无本地密码，可通过重置密码流程设置)
+async fn create_oidc_user(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    claims: &IdTokenClaims,
+    invited_by: Option<i32>,
+) -> Result<i32, AppError> {
+    // 用户名取 preferred_username / 邮箱前缀 / name，只保留安全字符
+    let base: String = [
+        claims.preferred_username.as_deref(),
+        claims.email.as_deref().and_then(|e| e.split('@').next()),
+        claims.name.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|s| {
+        s.chars()
+            .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            .take(16)
+            .collect::<String>()
+    })
+    .find(|s| s.len() >= 3)
+    .unwrap_or_else(|| "user".into());
+
+    // 只有身份提供方确认过、且本地未被占用的邮箱才写入
+    let email = match claims.email.as_deref().filter(|_| claims.email_verified) {
+        Some(email) => {
+            let taken = sqlx::query("SELECT id FROM users WHERE LOWER(email) = LOWER($1)")
+                .bind(email)
+                .fetch_optional(&mut **tx)
+                .await?;
+            taken.is_none().then(|| email.to_string())
+        }
+        None => None,
+    };
+
+    for attempt in 0..5 {
+        let username = if attempt == 0 {
+            base.clone()
+        } else {
+            format!("{}{:04}", base, OsRng.next_u32() % 10000)
+        };
+        let inserted: Option<(i32,)> = sqlx::query_as(
+            r#"INSERT INTO users (username, password_hash, email, email_verified_at, invited_by)
+               VALUES ($1, '', $2, CASE WHEN $2::VARCHAR IS NULL THEN NULL ELSE NOW() END, $3)
+               ON CONFLICT (username) DO NOTHING RETURNING id"#,
+        )
+        .bind(&username)
+        .bind(&email)
+        .bind(invited_by)
+        .fetch_optional(&mut **tx)
+        .await?;
+        if let Some((id,)) = inserted {
+            create_personal_workspace(tx, id, &username).await?;
+            return Ok(id);
+        }
+    }
+    Err(AppError::Internal)
+}
+
+pub async fn get_identities_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<UserIdentity>>, AppError> {
+    user.require_session()?;
+    let identities = sqlx::query_as::<_, UserIdentity>(
+        r#"SELECT id, issuer, subject, email, created_at, last_login_at
+           FROM user_identities WHERE user_id = $1 ORDER BY created_at"#,
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(identities))
+}
+
+/// 解除关联；没有本地密码的用户不能解除最后一个第三方身份
+pub async fn delete_identity_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    user.require_session()?;
+
+    let mut tx = state.db.begin().await?;
+    let (has_password, identity_count): (bool, i64) = sqlx::query_as(
+        r#"SELECT u.password_hash <> '', (SELECT COUNT(*) FROM user_identities WHERE user_id = u.id)
+           FROM users u WHERE u.id = $1 FOR UPDATE"#,
+    )
+    .bind(user.id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if !has_password && identity_count <= 1 {
+        return Err(AppError::BadRequest("请先设置密码，再解除最后一个第三方账号".into()));
+    }
+
+    let res = sqlx::query("DELETE FROM user_identities WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+    if res.rows_affected() == 0 { return Err(AppError::NotFound("关联不存在".into())); }
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ======= TWO-FACTOR HANDLERS =======
+
+/// 每次生成的恢复码数量
+const RECOVERY_CODE_COUNT: usize = 10;
+
+#[derive(sqlx::FromRow)]
+struct TotpRow {
+    totp_secret: Option<String>,
+    totp_enabled_at: Option<DateTime<Utc>>,
+    totp_last_step: Option<i64>,
+}
+
+/// 校验第二因素：6 位 TOTP 验证码或一次性恢复码
+/// 成功时在事务中记录已用的时间步或恢复码，由调用方提交
+async fn verify_second_factor(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: i32,
+    code: &str,
+) -> Result<bool, AppError> {
+    let row = sqlx::query_as::<_, TotpRow>(
+        "SELECT totp_secret, totp_enabled_at, totp_last_step FROM users WHERE id = $1 FOR UPDATE",
+    )
+    .bind(user_id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let secret = match (row.totp_secret, row.totp_enabled_at) {
+        (Some(secret), Some(_)) => secret,
+        _ => return Ok(false),
+    };
+
+    let code = code.trim();
+    if let Some(step) = totp::verify(&secret, code, row.totp_last_step) {
+        sqlx::query("UPDATE users SET totp_last_step = $1 WHERE id = $2")
+            .bind(step)
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await?;
+        return Ok(true);
+    }
+
+    let res = sqlx::query(
+        "UPDATE totp_recovery_codes SET used_at = NOW() WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+    )
+    .bind(user_id)
+    .bind(hash_token(&totp::normalize_recovery_code(code)))
+    .execute(&mut **tx)
+    .await?;
+    Ok(res.rows_affected() == 1)
+}
+
+/// 开始启用两步验证：生成密钥 (待确认)，返回供验证器 App 扫码的 URI
+pub async fn setup_2fa_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<TwoFactorSetupResponse>, AppError> {
+    user.require_session()?;
+    let secret = totp::generate_secret();
+    let res = sqlx::query(
+        "UPDATE users SET totp_secret = $1, totp_last_step = NULL WHERE id = $2 AND totp_enabled_at IS NULL",
+    )
+    .bind(&secret)
+    .bind(user.id)
+    .execute(&state.db)
+    .await?;
+    if res.rows_affected() == 0 { return Err(AppError::BadRequest("两步验证已开启".into())); }
+
+    let otpauth_uri = totp::otpauth_uri(&secret, &user.username).map_err(|_| AppError::Internal)?;
+    Ok(Json(TwoFactorSetupResponse { secret, otpauth_uri }))
+}
+
+/// 提交验证码确认启用，返回一次性恢复码 (只展示这一次)
+pub async fn confirm_2fa_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<TwoFactorCodeSchema>,
+) -> Result<Json<RecoveryCodesResponse>, AppError> {
+    user.require_session()?;
+    let mut tx = state.db.begin().await?;
+    let row = sqlx::query_as::<_, TotpRow>(
+        "SELECT totp_secret, totp_enabled_at, totp_last_step FROM users WHERE id = $1 FOR UPDATE",
     )
     .bind(user.id)
-    .fetch_all(&state.db)
+    .fetch_one(&mut *tx)
     .await?;
-    Ok(Json(projects))
+
+    if row.totp_enabled_at.is_some() { return Err(AppError::BadRequest("两步验证已开启".into())); }
+    let secret = row.totp_secret.ok_or_else(|| AppError::BadRequest("请先获取两步验证密钥".into()))?;
+    let step = totp::verify(&secret, body.code.trim(), None)
+        .ok_or_else(|| AppError::BadRequest("验证码错误".into()))?;
+
+    sqlx::query("UPDATE users SET totp_enabled_at = NOW(), totp_last_step = $1 WHERE id = $2")
+        .bind(step)
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+
+    let recovery_codes = totp::generate_recovery_codes(RECOVERY_CODE_COUNT);
+    sqlx::query("DELETE FROM totp_recovery_codes WHERE user_id = $1")
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+    for code in &recovery_codes {
+        sqlx::query("INSERT INTO totp_recovery_codes (user_id, code_hash) VALUES ($1, $2)")
+            .bind(user.id)
+            .bind(hash_token(&totp::normalize_recovery_code(code)))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
 }
 
-pub async fn create_project_handler(
+/// 关闭两步验证：需要同时提供密码和验证码 (或恢复码)
+pub async fn disable_2fa_handler(
     user: AuthUser,
     State(state): State<AppState>,
-    ValidatedJson(body): ValidatedJson<CreateProjectSchema>,
-) -> Result<Json<Project>, AppError> {
-    let project = sqlx::query_as::<_, Project>(
-        "INSERT INTO projects (user_id, name, description, color) VALUES ($1, $2, $3, $4) RETURNING *",
+    ValidatedJson(body): ValidatedJson<DisableTwoFactorSchema>,
+) -> Result<StatusCode, AppError> {
+    user.require_session()?;
+    let account = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_one(&state.db)
+        .await?;
+    if account.totp_enabled_at.is_none() { return Err(AppError::BadRequest("两步验证未开启".into())); }
+    if !verify_password(&body.password, &account.password_hash) {
+        return Err(AppError::BadRequest("密码错误".into()));
+    }
+
+    let mut tx = state.db.begin().await?;
+    if !verify_second_factor(&mut tx, user.id, &body.code).await? {
+        return Err(AppError::BadRequest("验证码错误".into()));
+    }
+    sqlx::query(
+        "UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL WHERE id = $1",
     )
     .bind(user.id)
-    .bind(body.name)
-    .bind(body.description)
-    .bind(body.color.unwrap_or_else(|| "#5E6AD2".to_string()))
-    .fetch_one(&state.db)
+    .execute(&mut *tx)
     .await?;
-    Ok(Json(project))
+    sqlx::query("DELETE FROM totp_recovery_codes WHERE user_id = $1")
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
-pub async fn update_project_handler(
-    user: AuthUser,
-    Path(id): Path<i32>,
+// ======= SESSION HANDLERS =======
+
+/// 退出当前设备：吊销提交的刷新令牌所在的整个会话
+/// 如果同时携带了有效的 Access Token，也将其加入黑名单
+pub async fn logout_handler(
+    user: Option<AuthUser>,
     State(state): State<AppState>,
-    ValidatedJson(body): ValidatedJson<UpdateProjectSchema>,
-) -> Result<Json<Project>, AppError> {
-    let project = sqlx::query_as::<_, Project>(
-        r#"UPDATE projects SET 
-            name = COALESCE($1, name),
-            description = COALESCE($2, description),
-            status = COALESCE($3, status),
-            color = COALESCE($4, color),
-            updated_at = NOW()
-         WHERE id = $5 AND user_id = $6
-         RETURNING *"#,
+    Json(payload): Json<RefreshRequest>,
+) -> Result<StatusCode, AppError> {
+    if let Some(AuthUser { id, credential: Credential::Jwt { jti, exp, .. }, .. }) = user {
+        state.revocation.deny(&state.db, id, jti, exp).await?;
+    }
+
+    sqlx::query(
+        r#"UPDATE refresh_tokens SET revoked_at = NOW()
+           WHERE family_id = (SELECT family_id FROM refresh_tokens WHERE token = $1)
+             AND revoked_at IS NULL"#,
     )
-    .bind(body.name).bind(body.description).bind(body.status).bind(body.color)
-    .bind(id).bind(user.id)
-    .fetch_optional(&state.db)
-    .await?
-    .ok_or_else(|| AppError::NotFound("项目未找到".into()))?;
+    .bind(&payload.refresh_token)
+    .execute(&state.db)
+    .await?;
 
-    Ok(Json(project))
+    // 无论令牌是否存在都返回成功，避免泄露令牌状态
+    Ok(StatusCode::NO_CONTENT)
 }
 
-pub async fn delete_project_handler(
+/// 退出所有设备：吊销全部刷新令牌，并使已签发的 Access Token 立即失效
+pub async fn logout_all_handler(
     user: AuthUser,
-    Path(id): Path<i32>,
     State(state): State<AppState>,
 ) -> Result<StatusCode, AppError> {
-    let res = sqlx::query("DELETE FROM projects WHERE id = $1 AND user_id = $2")
-        .bind(id)
-        .bind(user.id)
-        .execute(&state.db)
-        .await?;
-    if res.rows_affected() == 0 { return Err(AppError::NotFound("项目不存在或无权操作".into())); }
+    user.require_session()?;
+    let mut tx = state.db.begin().await?;
+    revoke_all_sessions(&state, &mut tx, user.id).await?;
+    tx.commit().await?;
+    state.revocation.invalidate(user.id);
     Ok(StatusCode::NO_CONTENT)
 }
 
-// ======= ISSUES HANDLERS =======
-
-pub async fn get_all_my_issues_handler(
+pub async fn get_sessions_handler(
     user: AuthUser,
     State(state): State<AppState>,
-) -> Result<Json<Vec<Issue>>, AppError> {
-    let issues = sqlx::query_as::<_, Issue>(
-        "SELECT * FROM issues WHERE user_id = $1 ORDER BY updated_at DESC",
+) -> Result<Json<Vec<Session>>, AppError> {
+    user.require_session()?;
+    // 每个会话只有一个未使用的有效令牌，它记录了最近一次使用的设备信息
+    let sessions = sqlx::query_as::<_, Session>(
+        r#"
+        SELECT r.family_id AS id,
+               (SELECT MIN(f.created_at) FROM refresh_tokens f WHERE f.family_id = r.family_id) AS created_at,
+               r.last_used_at, r.user_agent, r.ip_address, r.expires_at,
+               COALESCE(r.family_id = $2, FALSE) AS current
+        FROM refresh_tokens r
+        WHERE r.user_id = $1
+          AND r.used_at IS NULL
+          AND r.revoked_at IS NULL
+          AND r.expires_at > NOW()
+        ORDER BY r.last_used_at DESC
+        "#,
     )
     .bind(user.id)
+    .bind(user.session_id())
     .fetch_all(&state.db)
     .await?;
 
-    Ok(Json(issues))
+    Ok(Json(sessions))
 }
 
-pub async fn get_project_issues_handler(
+pub async fn delete_session_handler(
     user: AuthUser,
-    Path(project_id): Path<i32>,
-    Query(query): Query<IssueQuery>,
+    Path(session_id): Path<Uuid>,
     State(state): State<AppState>,
-) -> Result<Json<Vec<Issue>>, AppError> {
-    let project_exists = sqlx::query("SELECT id FROM projects WHERE id = $1 AND user_id = $2")
-        .bind(project_id)
-        .bind(user.id)
-        .fetch_optional(&state.db)
-        .await?;
+) -> Result<StatusCode, AppError> {
+    user.require_session()?;
+    let res = sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND family_id = $2 AND revoked_at IS NULL",
+    )
+    .bind(user.id)
+    .bind(session_id)
+    .execute(&state.db)
+    .await?;
+    if res.rows_affected() == 0 { return Err(AppError::NotFound("会话不存在或已失效".into())); }
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    if project_exists.is_none() { return Err(AppError::Forbidden("无权访问该项目".into())); }
+// ======= INVITE HANDLERS =======
 
-    let issues = sqlx::query_as::<_, Issue>(
-        r#"
-        SELECT * FROM issues 
-        WHERE project_id = $1 
-          AND ($2 IS NULL OR title ILIKE $2 OR description ILIKE $2)
-          AND ($3 IS NULL OR status = $3)
-        ORDER BY priority DESC, created_at DESC
-        "#,
+/// 邀请码默认有效天数
+const INVITE_DEFAULT_TTL_DAYS: i64 = 7;
+/// 普通用户同时持有的有效邀请码上限
+const INVITE_MAX_ACTIVE_PER_USER: i64 = 10;
+
+pub async fn get_invites_handler(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<InviteCode>>, AppError> {
+    user.require_session()?;
+    let invites = sqlx::query_as::<_, InviteCode>(
+        r#"SELECT id, code_prefix, max_uses, use_count, expires_at, revoked_at, created_at
+           FROM invite_codes WHERE created_by = $1 ORDER BY created_at DESC"#,
     )
-    .bind(project_id)
-    .bind(query.q.map(|s| format!("%{}%", s)))
-    .bind(query.status)
+    .bind(user.id)
     .fetch_all(&state.db)
     .await?;
-
-    Ok(Json(issues))
+    Ok(Json(invites))
 }
 
-pub async fn create_issue_handler(
+/// 创建邀请码，明文只在此响应中返回一次
+/// 普通用户只能创建单次使用的邀请码，且有数量上限
+pub async fn create_invite_handler(
     user: AuthUser,
     State(state): State<AppState>,
-    ValidatedJson(body): ValidatedJson<CreateIssueSchema>,
-) -> Result<Json<Issue>, AppError> {
-    let project_owned = sqlx::query("SELECT id FROM projects WHERE id = $1 AND user_id = $2")
-        .bind(body.project_id)
+    ValidatedJson(body): ValidatedJson<CreateInviteSchema>,
+) -> Result<Json<CreatedInvite>, AppError> {
+    user.require_session()?;
+    if state.config.registration == RegistrationPolicy::Closed {
+        return Err(AppError::Forbidden("当前不开放注册".into()));
+    }
+
+    let max_uses = body.max_uses.unwrap_or(1);
+    if user.role != Role::Admin {
+        if max_uses > 1 {
+            return Err(AppError::Forbidden("只有管理员可以创建多次使用的邀请码".into()));
+        }
+        let (active,): (i64,) = sqlx::query_as(
+            r#"SELECT COUNT(*) FROM invite_codes
+               WHERE created_by = $1 AND revoked_at IS NULL AND expires_at > NOW() AND use_count < max_uses"#,
+        )
         .bind(user.id)
-        .fetch_optional(&state.db)
+        .fetch_one(&state.db)
         .await?;
+        if active >= INVITE_MAX_ACTIVE_PER_USER {
+            return Err(AppError::BadRequest(format!(
+                "最多同时持有 {} 个未使用的邀请码",
+                INVITE_MAX_ACTIVE_PER_USER
+            )));
+        }
+    }
 
-    if project_owned.is_none() { return Err(AppError::BadRequest("目标项目不存在".into())); }
+    let code = generate_secure_token();
+    let expires_at = Utc::now() + Duration::days(body.expires_in_days.unwrap_or(INVITE_DEFAULT_TTL_DAYS));
 
-    let issue = sqlx::query_as::<_, Issue>(
-        r#"INSERT INTO issues (project_id, user_id, title, description, priority, due_date) 
-           VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"#,
+    let info = sqlx::query_as::<_, InviteCode>(
+        r#"INSERT INTO invite_codes (created_by, code_hash, code_prefix, max_uses, expires_at)
+           VALUES ($1, $2, $3, $4, $5)
+           RETURNING id, code_prefix, max_uses, use_count, expires_at, revoked_at, created_at"#,
     )
-    .bind(body.project_id)
     .bind(user.id)
-    .bind(body.title)
-    .bind(body.description)
-    .bind(body.priority.unwrap_or(0))
-    .bind(body.due_date)
+    .bind(hash_token(&code))
+    .bind(code.chars().take(8).collect::<String>())
+    .bind(max_uses)
+    .bind(expires_at)
     .fetch_one(&state.db)
     .await?;
 
-    Ok(Json(issue))
-}
-
-pub async fn update_issue_handler(
-    user: AuthUser,
-    Path(id): Path<i32>,
-    State(state): State<AppState>,
-    ValidatedJson(body): ValidatedJson<UpdateIssueSchema>,
-) -> Result<Json<Issue>, AppError> {
-    let issue = sqlx::query_as::<_, Issue>(
-        r#"UPDATE issues SET 
-            title = COALESCE($1, title),
-            description = CASE WHEN $2 IS NULL THEN description ELSE $2 END,
-            status = COALESCE($3, status),
-            priority = COALESCE($4, priority),
-            due_date = COALESCE($5, due_date),
-            updated_at = NOW()
-         WHERE id = $6 AND user_id = $7
-         RETURNING *"#,
-    )
-    .bind(body.title).bind(body.description).bind(body.status).bind(body.priority).bind(body.due_date)
-    .bind(id).bind(user.id)
-    .fetch_optional(&state.db)
-    .await?
-    .ok_or_else(|| AppError::NotFound("任务未找到".into()))?;
-
-    Ok(Json(issue))
+    Ok(Json(CreatedInvite { code, info }))
 }
 
-pub async fn delete_issue_handler(
+/// 作废自己创建的邀请码
+pub async fn revoke_invite_handler(
     user: AuthUser,
     Path(id): Path<i32>,
     State(state): State<AppState>,
 ) -> Result<StatusCode, AppError> {
-    let res = sqlx::query("DELETE FROM issues WHERE id = $1 AND user_id = $2")
-        .bind(id).bind(user.id).execute(&state.db).await?;
-    if res.rows_affected() == 0 { return Err(AppError::NotFound("任务未找到".into())); }
+    user.require_session()?;
+    let res = sqlx::query(
+        "UPDATE invite_codes SET revoked_at = COALESCE(revoked_at, NOW()) WHERE id = $1 AND created_by = $2",
+    )
+    .bind(id)
+    .bind(user.id)
+    .execute(&state.db)
+    .await?;
+    if res.rows_affected() == 0 { return Err(AppError::NotFound("邀请码不存在".into())); }
     Ok(StatusCode::NO_CONTENT)
 }
 
-// ======= AUTH HANDLERS (无感刷新版本) =======
+// ======= PERSONAL ACCESS TOKEN HANDLERS =======
 
-pub async fn register_handler(
+pub async fn get_personal_tokens_handler(
+    user: AuthUser,
     State(state): State<AppState>,
-    ValidatedJson(payload): ValidatedJson<RegisterSchema>,
-) -> Result<Json<serde_json::Value>, AppError> {
-    let hashed_password = hash_password(&payload.password).map_err(|_| AppError::Internal)?;
-    sqlx::query("INSERT INTO users (username, password_hash) VALUES ($1, $2)")
-        .bind(&payload.username).bind(hashed_password).execute(&state.db).await?;
-    Ok(Json(json!({"message": "User registered successfully"})))
+) -> Result<Json<Vec<PersonalAccessToken>>, AppError> {
+    user.require_session()?;
+    let tokens = sqlx::query_as::<_, PersonalAccessToken>(
+        r#"SELECT id, name, token_prefix, scopes, expires_at, last_used_at, created_at
+           FROM personal_access_tokens WHERE user_id = $1 ORDER BY created_at DESC"#,
+    )
+    .bind(user.id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(tokens))
 }
 
-pub async fn login_handler(
+/// 创建个人访问令牌，明文只在此响应中返回一次
+pub async fn create_personal_token_handler(
+    user: AuthUser,
     State(state): State<AppState>,
-    ValidatedJson(payload): ValidatedJson<LoginSchema>,
-) -> Result<Json<AuthResponse>, AppError> {
-    // 1. 验证用户
-    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
-        .bind(&payload.username).fetch_optional(&state.db).await?
-        .ok_or_else(|| AppError::Auth("用户名或密码错误".into()))?;
-
-    if !verify_password(&payload.password, &user.password_hash) {
-        return Err(AppError::Auth("用户名或密码错误".into()));
-    }
+    ValidatedJson(body): ValidatedJson<CreatePersonalTokenSchema>,
+) -> Result<Json<CreatedPersonalToken>, AppError> {
+    user.require_session()?;
 
-    // 2. 生成 Access Token (短效)
-    let token = create_jwt(user.id, &user.username).map_err(|_| AppError::Internal)?;
+    let mut scopes: Vec<&str> = body.scopes.iter().map(|s| s.as_str()).collect();
+    scopes.sort_unstable();
+    scopes.dedup();
 
-    // 3. 生成并存储 Refresh Token (长效)
-    let refresh_token_str = generate_refresh_token();
-    let expires_at = Utc::now() + Duration::days(7); // 7天有效期
+    let token = generate_personal_access_token();
+    let expires_at = body.expires_in_days.map(|days| Utc::now() + Duration::days(days));
 
-    sqlx::query(
-        "INSERT INTO refresh_tokens (user_id, token, expires_at) VALUES ($1, $2, $3)"
+    let info = sqlx::query_as::<_, PersonalAccessToken>(
+        r#"INSERT INTO personal_access_tokens (user_id, name, token_hash, token_prefix, scopes, expires_at)
+           VALUES ($1, $2, $3, $4, $5, $6)
+           RETURNING id, name, token_prefix, scopes, expires_at, last_used_at, created_at"#,
     )
     .bind(user.id)
-    .bind(&refresh_token_str)
+    .bind(body.name)
+    .bind(hash_token(&token))
+    .bind(token.chars().take(12).collect::<String>())
+    .bind(scopes)
     .bind(expires_at)
-    .execute(&state.db)
+    .fetch_one(&state.db)
     .await?;
 
-    Ok(Json(AuthResponse { 
-        token, 
-        refresh_token: Some(refresh_token_str),
-        username: user.username 
-    }))
+    Ok(Json(CreatedPersonalToken { token, info }))
 }
 
-// 核心：无感刷新接口
-pub async fn refresh_handler(
+pub async fn delete_personal_token_handler(
+    user: AuthUser,
+    Path(id): Path<i32>,
     State(state): State<AppState>,
-    Json(payload): Json<RefreshRequest>,
-) -> Result<Json<AuthResponse>, AppError> {
-    // 1. 检查数据库中是否存在该 Token 且未过期
-    let row = sqlx::query!(
-        r#"SELECT r.user_id, u.username FROM refresh_tokens r
-           JOIN users u ON r.user_id = u.id
-           WHERE r.token = $1 AND r.expires_at > NOW()"#,
-        payload.refresh_token
-    )
-    .fetch_optional(&state.db)
-    .await?
-    .ok_or_else(|| AppError::Auth("登录已过期，请重新登录".into()))?;
-
-    // 2. 签发新的 Access Token
-    let new_access_token = create_jwt(row.user_id, &row.username).map_err(|_| AppError::Internal)?;
-
-    // 3. 返回新 Token (这里沿用旧的 Refresh Token，也可以在这里进行滚动更新)
-    Ok(Json(AuthResponse {
-        token: new_access_token,
-        refresh_token: Some(payload.refresh_token),
-        username: row.username,
-    }))
+) -> Result<StatusCode, AppError> {
+    user.require_session()?;
+    let res = sqlx::query("DELETE FROM personal_access_tokens WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user.id)
+        .execute(&state.db)
+        .await?;
+    if res.rows_affected() == 0 { return Err(AppError::NotFound("访问令牌不存在".into())); }
+    Ok(StatusCode::NO_CONTENT)
 }
 
 pub async fn get_issue_comments_handler(
@@ -277,20 +3675,18 @@ pub async fn get_issue_comments_handler(
     Path(issue_id): Path<i32>,
     State(state): State<AppState>,
 ) -> Result<Json<Vec<Comment>>, AppError> {
-    // 检查 Issue 是否存在且用户有权访问（通过项目所属权判断）
+    user.require_scope(Scope::IssuesRead)?;
+    authorize_issue(&state.db, &user, issue_id, ProjectRole::Viewer).await?;
     let comments = sqlx::query_as::<_, Comment>(
         r#"
         SELECT c.*, u.username 
         FROM comments c
         JOIN users u ON c.user_id = u.id
-        JOIN issues i ON c.issue_id = i.id
-        JOIN projects p ON i.project_id = p.id
-        WHERE c.issue_id = $1 AND p.user_id = $2
+        WHERE c.issue_id = $1
         ORDER BY c.created_at ASC
         "#
     )
     .bind(issue_id)
-    .bind(user.id)
     .fetch_all(&state.db)
     .await?;
 
@@ -303,6 +3699,9 @@ pub async fn create_comment_handler(
     State(state): State<AppState>,
     ValidatedJson(body): ValidatedJson<CreateCommentSchema>,
 ) -> Result<Json<Comment>, AppError> {
+    user.require_scope(Scope::IssuesWrite)?;
+    user.require_verified_email(&state).await?;
+    authorize_issue(&state.db, &user, issue_id, ProjectRole::Commenter).await?;
     // 插入评论
     let comment = sqlx::query_as::<_, Comment>(
         r#"
@@ -326,11 +3725,15 @@ pub async fn create_comment_handler(
 
 pub async fn unified_search_handler(
     user: AuthUser,
+    WorkspaceScope(workspace_id): WorkspaceScope,
     Query(query): Query<IssueQuery>, // 复用包含 q 的 Query 结构
     State(state): State<AppState>,
 ) -> Result<Json<Vec<UnifiedSearchResult>>, AppError> {
+    user.require_scope(Scope::ProjectsRead)?;
+    user.require_scope(Scope::IssuesRead)?;
+    let label_names = query.label_names();
     let q = query.q.unwrap_or_default();
-    if q.trim().is_empty() {
+    if q.trim().is_empty() && label_names.is_none() {
         return Ok(Json(vec![]));
     }
 
@@ -338,26 +3741,235 @@ pub async fn unified_search_handler(
 
     // 使用 UNION ALL 将项目和任务的结果合并
     // 注意：字段数量和类型必须对齐
-    let results = sqlx::query_as::<_, UnifiedSearchResult>(
+    // 按标签筛选时只返回任务
+    let results = sqlx::query_as::<_, UnifiedSearchResult>(&format!(
         r#"
-        SELECT 'project' as type, id, name as title, description, status, color 
-        FROM projects 
-        WHERE user_id = $1 AND (name ILIKE $2 OR description ILIKE $2)
+        WITH visible AS (
+            SELECT p.* FROM projects p
+            WHERE p.id IN ({})
+              AND ($3::INTEGER IS NULL OR p.workspace_id = $3)
+        )
+        SELECT 'project' as type, p.id, p.name as title, p.description, p.status, p.color 
+        FROM visible p
+        WHERE $4::TEXT[] IS NULL AND (p.name ILIKE $2 OR p.description ILIKE $2)
         
         UNION ALL
         
-        SELECT 'issue' as type, id, title, description, status, NULL as color 
-        FROM issues 
-        WHERE user_id = $1 AND (title ILIKE $2 OR description ILIKE $2)
+        SELECT 'issue' as type, i.id, i.title, i.description, i.status, NULL as color 
+        FROM issues i
+        JOIN visible p ON p.id = i.project_id
+        WHERE (i.title ILIKE $2 OR i.description ILIKE $2) AND {}
         
         ORDER BY title ASC
         LIMIT 15
-        "#
-    )
+        "#,
+        VISIBLE_PROJECTS_SQL,
+        issue_has_labels_sql("$4")
+    ))
     .bind(user.id)
     .bind(search_pattern)
+    .bind(workspace_id)
+    .bind(label_names)
     .fetch_all(&state.db)
     .await?;
 
     Ok(Json(results))
 }
+
+// ======= ADMIN HANDLERS =======
+
+const ADMIN_USER_COLUMNS: &str = r#"
+    u.id, u.username, u.email, u.email_verified_at, u.display_name, u.role,
+    u.totp_enabled_at IS NOT NULL AS two_factor_enabled,
+    u.disabled_at, u.deletion_scheduled_at, u.suspended_at,
+    (SELECT MAX(r.last_used_at) FROM refresh_tokens r WHERE r.user_id = u.id) AS last_active_at,
+    u.created_at"#;
+
+async fn fetch_admin_user_view(state: &AppState, user_id: i32) -> Result<AdminUserView, AppError> {
+    sqlx::query_as::<_, AdminUserView>(&format!("SELECT {} FROM users u WHERE u.id = $1", ADMIN_USER_COLUMNS))
+        .bind(user_id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("用户不存在".into()))
+}
+
+/// 管理员不能对自己执行停用、改角色等操作，避免把自己锁在外面
+fn ensure_not_self(admin: &AuthUser, user_id: i32) -> Result<(), AppError> {
+    if admin.id == user_id {
+        return Err(AppError::BadRequest("不能对自己执行该操作".into()));
+    }
+    Ok(())
+}
+
+pub async fn admin_list_users_handler(
+    AdminUser(_admin): AdminUser,
+    Query(query): Query<AdminUserQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AdminUserView>>, AppError> {
+    let pattern = query.q.filter(|q| !q.trim().is_empty()).map(|q| format!("%{}%", q.trim()));
+    let users = sqlx::query_as::<_, AdminUserView>(&format!(
+        r#"SELECT {} FROM users u
+           WHERE ($1::TEXT IS NULL OR u.username ILIKE $1 OR u.email ILIKE $1 OR u.display_name ILIKE $1)
+             AND ($2::VARCHAR IS NULL OR u.role = $2)
+             AND ($3::TEXT IS NULL
+                  OR ($3 = 'active' AND u.disabled_at IS NULL)
+                  OR ($3 = 'disabled' AND u.disabled_at IS NOT NULL)
+                  OR ($3 = 'pending_deletion' AND u.deletion_scheduled_at IS NOT NULL))
+           ORDER BY u.id
+           LIMIT $4 OFFSET $5"#,
+        ADMIN_USER_COLUMNS
+    ))
+    .bind(pattern)
+    .bind(query.role)
+    .bind(query.status.map(|s| s.as_str()))
+    .bind(query.limit.unwrap_or(50).clamp(1, 200))
+    .bind(query.offset.unwrap_or(0).max(0))
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(users))
+}
+
+pub async fn admin_get_user_handler(
+    AdminUser(_admin): AdminUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<AdminUserView>, AppError> {
+    Ok(Json(fetch_admin_user_view(&state, id).await?))
+}
+
+/// 停用账户并立即下线其所有设备
+pub async fn admin_disable_user_handler(
+    AdminUser(admin): AdminUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<AdminUserView>, AppError> {
+    ensure_not_self(&admin, id)?;
+    let mut tx = state.db.begin().await?;
+    // 单独记录管理员停用，用户不能通过撤销注销申请解除
+    let res = sqlx::query(
+        "UPDATE users SET disabled_at = COALESCE(disabled_at, NOW()), suspended_at = COALESCE(suspended_at, NOW()) WHERE id = $1",
+    )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound("用户不存在".into()));
+    }
+    revoke_all_sessions(&state, &mut tx, id).await?;
+    tx.commit().await?;
+    state.revocation.invalidate(id);
+
+    tracing::info!("管理员 {} 停用了用户 {}", admin.id, id);
+    Ok(Json(fetch_admin_user_view(&state, id).await?))
+}
+
+/// 重新启用账户 (同时取消待处理的注销申请)
+pub async fn admin_enable_user_handler(
+    AdminUser(admin): AdminUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<AdminUserView>, AppError> {
+    let res = sqlx::query("UPDATE users SET disabled_at = NULL, deletion_scheduled_at = NULL, suspended_at = NULL WHERE id = $1")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound("用户不存在".into()));
+    }
+
+    tracing::info!("管理员 {} 启用了用户 {}", admin.id, id);
+    Ok(Json(fetch_admin_user_view(&state, id).await?))
+}
+
+/// 强制用户在所有设备上退出登录
+pub async fn admin_logout_user_handler(
+    AdminUser(admin): AdminUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    fetch_admin_user_view(&state, id).await?;
+    let mut tx = state.db.begin().await?;
+    revoke_all_sessions(&state, &mut tx, id).await?;
+    tx.commit().await?;
+    state.revocation.invalidate(id);
+
+    tracing::info!("管理员 {} 强制用户 {} 退出所有设备", admin.id, id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 关闭用户的两步验证并删除恢复码 (用户丢失验证器时使用)
+pub async fn admin_reset_2fa_handler(
+    AdminUser(admin): AdminUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> Result<Json<AdminUserView>, AppError> {
+    let mut tx = state.db.begin().await?;
+    let res = sqlx::query(
+        "UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL WHERE id = $1",
+    )
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound("用户不存在".into()));
+    }
+    sqlx::query("DELETE FROM totp_recovery_codes WHERE user_id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    tracing::info!("管理员 {} 重置了用户 {} 的两步验证", admin.id, id);
+    Ok(Json(fetch_admin_user_view(&state, id).await?))
+}
+
+/// 修改用户角色；已签发的 Access Token 立即失效，刷新后携带新角色
+pub async fn admin_update_role_handler(
+    AdminUser(admin): AdminUser,
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<UpdateRoleSchema>,
+) -> Result<Json<AdminUserView>, AppError> {
+    ensure_not_self(&admin, id)?;
+    let mut tx = state.db.begin().await?;
+    let res = sqlx::query("UPDATE users SET role = $1 WHERE id = $2")
+        .bind(body.role)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::NotFound("用户不存在".into()));
+    }
+    state.revocation.bump_version(&mut *tx, id).await?;
+    tx.commit().await?;
+    state.revocation.invalidate(id);
+
+    tracing::info!("管理员 {} 将用户 {} 的角色改为 {:?}", admin.id, id, body.role);
+    Ok(Json(fetch_admin_user_view(&state, id).await?))
+}
+
+pub async fn admin_stats_handler(
+    AdminUser(_admin): AdminUser,
+    State(state): State<AppState>,
+) -> Result<Json<InstanceStats>, AppError> {
+    let stats = sqlx::query_as::<_, InstanceStats>(
+        r#"SELECT
+            (SELECT COUNT(*) FROM users) AS users_total,
+            (SELECT COUNT(*) FROM users WHERE role = 'admin') AS admins,
+            (SELECT COUNT(*) FROM users WHERE disabled_at IS NOT NULL) AS users_disabled,
+            (SELECT COUNT(*) FROM users WHERE deletion_scheduled_at IS NOT NULL) AS users_pending_deletion,
+            (SELECT COUNT(*) FROM users WHERE totp_enabled_at IS NOT NULL) AS users_with_2fa,
+            (SELECT COUNT(*) FROM users WHERE created_at > NOW() - INTERVAL '30 days') AS users_registered_30d,
+            (SELECT COUNT(DISTINCT user_id) FROM refresh_tokens
+              WHERE last_used_at > NOW() - INTERVAL '30 days') AS users_active_30d,
+            (SELECT COUNT(*) FROM refresh_tokens
+              WHERE used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()) AS active_sessions,
+            (SELECT COUNT(*) FROM personal_access_tokens) AS personal_access_tokens,
+            (SELECT COUNT(*) FROM projects) AS projects,
+            (SELECT COUNT(*) FROM issues) AS issues,
+            (SELECT COUNT(*) FROM comments) AS comments"#,
+    )
+    .fetch_one(&state.db)
+    .await?;
+    Ok(Json(stats))
+}
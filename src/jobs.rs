@@ -0,0 +1,105 @@
+// src/jobs.rs
+// 后台定时任务 (随服务启动)
+use sqlx::PgPool;
+use std::time::Duration;
+
+use crate::access;
+use crate::rank;
+use crate::AppError;
+
+/// 清理任务的执行间隔
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 定期永久删除注销宽限期已结束的账户 (关联数据随外键级联删除)
+pub fn spawn_account_purge(db: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match purge_deleted_accounts(&db).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("已永久删除 {} 个注销账户", count),
+                Err(e) => tracing::error!("清理注销账户失败: {:?}", e),
+            }
+        }
+    });
+}
+
+async fn purge_deleted_accounts(db: &PgPool) -> Result<u64, sqlx::Error> {
+    let due: Vec<i32> = sqlx::query_scalar("SELECT id FROM users WHERE deletion_scheduled_at <= NOW() ORDER BY id")
+        .fetch_all(db)
+        .await?;
+    let mut purged = 0;
+    for user_id in due {
+        let mut tx = db.begin().await?;
+        // 先交接工作区与项目所有权；仍有无法交接的共享项目时暂不删除，下次再试
+        match access::release_account_ownership(&mut tx, user_id).await {
+            Ok(()) => {}
+            Err(AppError::Database(e)) => return Err(e),
+            Err(e) => {
+                tracing::warn!("注销账户暂不能删除: user_id={}, {}", user_id, e);
+                continue;
+            }
+        }
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        purged += 1;
+    }
+    Ok(purged)
+}
+
+/// 排序键检查的执行间隔
+const RANK_REBALANCE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 定期为排序键过长的项目重新均匀分配排序键 (保持原有顺序)
+pub fn spawn_rank_rebalance(db: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RANK_REBALANCE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match rebalance_long_ranks(&db).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("已重新平衡 {} 个项目的任务排序", count),
+                Err(e) => tracing::error!("重新平衡任务排序失败: {:?}", e),
+            }
+        }
+    });
+}
+
+async fn rebalance_long_ranks(db: &PgPool) -> Result<usize, sqlx::Error> {
+    let projects: Vec<(i32,)> = sqlx::query_as("SELECT DISTINCT project_id FROM issues WHERE LENGTH(rank) > $1")
+        .bind(rank::REBALANCE_RANK_LENGTH)
+        .fetch_all(db)
+        .await?;
+    for &(project_id,) in &projects {
+        rebalance_project_ranks(db, project_id).await?;
+    }
+    Ok(projects.len())
+}
+
+async fn rebalance_project_ranks(db: &PgPool, project_id: i32) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    // 与拖拽排序、创建任务使用同一把项目锁
+    sqlx::query("SELECT id FROM projects WHERE id = $1 FOR UPDATE")
+        .bind(project_id)
+        .execute(&mut *tx)
+        .await?;
+    let ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM issues WHERE project_id = $1 ORDER BY rank, id")
+        .bind(project_id)
+        .fetch_all(&mut *tx)
+        .await?;
+    // 只修改排序键，不会更新任务的 updated_at
+    sqlx::query(
+        r#"UPDATE issues SET rank = data.rank
+           FROM UNNEST($1::INTEGER[], $2::TEXT[]) AS data(id, rank)
+           WHERE issues.id = data.id"#,
+    )
+    .bind(&ids)
+    .bind(rank::spread(ids.len()))
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
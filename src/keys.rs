@@ -0,0 +1,160 @@
+// src/keys.rs
+// JWT 签名密钥管理：从磁盘加载多把非对称密钥 (RS256 / EdDSA)
+// 使用 active 密钥签名，使用任意未退役的密钥验签，并以 JWKS 形式公开公钥
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// 密钥状态
+/// - active: 用于签名 (有且只有一把)
+/// - verify: 仅用于验签，轮换前预发布的新密钥或刚轮换下来的旧密钥
+/// - retired: 已退役，不再接受其签发的 Token，也不出现在 JWKS 中
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyStatus {
+    Active,
+    Verify,
+    Retired,
+}
+
+/// keys.json 中的单条记录
+#[derive(Debug, Deserialize)]
+struct KeyEntry {
+    kid: String,
+    alg: String,
+    /// PKCS#8 (或 RSA 的 PKCS#1) 格式的私钥 PEM 文件，相对于密钥目录
+    file: String,
+    status: KeyStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyManifest {
+    keys: Vec<KeyEntry>,
+}
+
+struct SigningKey {
+    kid: String,
+    alg: Algorithm,
+    status: KeyStatus,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    /// 公钥的 JWK 表示
+    jwk: Value,
+}
+
+pub struct KeyManager {
+    keys: Vec<SigningKey>,
+    active: usize,
+}
+
+impl KeyManager {
+    /// 从 JWT_KEYS_DIR 指向的目录加载密钥，缺少配置或密钥无效时返回错误 (启动失败)
+    pub fn from_env() -> Result<Self, String> {
+        let dir = std::env::var("JWT_KEYS_DIR").map_err(|_| "JWT_KEYS_DIR must be set".to_string())?;
+        Self::load(Path::new(&dir))
+    }
+
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let manifest_path = dir.join("keys.json");
+        let manifest = std::fs::read_to_string(&manifest_path)
+            .map_err(|e| format!("无法读取 {}: {}", manifest_path.display(), e))?;
+        let manifest: KeyManifest = serde_json::from_str(&manifest)
+            .map_err(|e| format!("{} 格式错误: {}", manifest_path.display(), e))?;
+
+        let mut keys = Vec::new();
+        for entry in manifest.keys {
+            if keys.iter().any(|k: &SigningKey| k.kid == entry.kid) {
+                return Err(format!("密钥 kid 重复: {}", entry.kid));
+            }
+            let pem = std::fs::read_to_string(dir.join(&entry.file))
+                .map_err(|e| format!("无法读取密钥 {}: {}", entry.kid, e))?;
+            keys.push(load_key(entry, &pem)?);
+        }
+
+        let mut active = keys.iter().enumerate().filter(|(_, k)| k.status == KeyStatus::Active);
+        let (index, _) = active.next().ok_or("没有状态为 active 的签名密钥")?;
+        if active.next().is_some() {
+            return Err("只能有一把状态为 active 的签名密钥".into());
+        }
+
+        for key in &keys {
+            tracing::info!("🔑 已加载 JWT 密钥 kid={} alg={:?} status={:?}", key.kid, key.alg, key.status);
+        }
+        Ok(KeyManager { keys, active: index })
+    }
+
+    /// 使用 active 密钥签名，Header 中带上 kid
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, String> {
+        let key = &self.keys[self.active];
+        let mut header = Header::new(key.alg);
+        header.kid = Some(key.kid.clone());
+        encode(&header, claims, &key.encoding).map_err(|e| e.to_string())
+    }
+
+    /// 根据 Header 中的 kid 选择未退役的密钥验签，算法必须与该密钥一致
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<TokenData<T>, String> {
+        let header = decode_header(token).map_err(|e| e.to_string())?;
+        let kid = header.kid.ok_or("Token 缺少 kid")?;
+        let key = self
+            .keys
+            .iter()
+            .find(|k| k.kid == kid && k.status != KeyStatus::Retired)
+            .ok_or_else(|| format!("未知或已退役的密钥: {}", kid))?;
+
+        decode::<T>(token, &key.decoding, &Validation::new(key.alg)).map_err(|e| e.to_string())
+    }
+
+    /// 公开的 JWK Set，包含所有未退役的密钥
+    pub fn jwks(&self) -> Value {
+        let keys: Vec<&Value> = self
+            .keys
+            .iter()
+            .filter(|k| k.status != KeyStatus::Retired)
+            .map(|k| &k.jwk)
+            .collect();
+        json!({ "keys": keys })
+    }
+}
+
+fn load_key(entry: KeyEntry, pem: &str) -> Result<SigningKey, String> {
+    let invalid = |e: &dyn std::fmt::Display| format!("密钥 {} 无效: {}", entry.kid, e);
+
+    let (alg, encoding, decoding, jwk) = match entry.alg.as_str() {
+        "RS256" => {
+            use rsa::pkcs1::DecodeRsaPrivateKey;
+            use rsa::pkcs8::DecodePrivateKey;
+            use rsa::traits::PublicKeyParts;
+
+            let private = rsa::RsaPrivateKey::from_pkcs8_pem(pem)
+                .or_else(|_| rsa::RsaPrivateKey::from_pkcs1_pem(pem))
+                .map_err(|e| invalid(&e))?;
+            let n = URL_SAFE_NO_PAD.encode(private.n().to_bytes_be());
+            let e = URL_SAFE_NO_PAD.encode(private.e().to_bytes_be());
+
+            let encoding = EncodingKey::from_rsa_pem(pem.as_bytes()).map_err(|e| invalid(&e))?;
+            let decoding = DecodingKey::from_rsa_components(&n, &e).map_err(|e| invalid(&e))?;
+            let jwk = json!({
+                "kty": "RSA", "use": "sig", "alg": "RS256", "kid": entry.kid, "n": n, "e": e,
+            });
+            (Algorithm::RS256, encoding, decoding, jwk)
+        }
+        "EdDSA" => {
+            use ed25519_dalek::pkcs8::DecodePrivateKey;
+
+            let private = ed25519_dalek::SigningKey::from_pkcs8_pem(pem).map_err(|e| invalid(&e))?;
+            let x = URL_SAFE_NO_PAD.encode(private.verifying_key().to_bytes());
+
+            let encoding = EncodingKey::from_ed_pem(pem.as_bytes()).map_err(|e| invalid(&e))?;
+            let decoding = DecodingKey::from_ed_components(&x).map_err(|e| invalid(&e))?;
+            let jwk = json!({
+                "kty": "OKP", "crv": "Ed25519", "use": "sig", "alg": "EdDSA", "kid": entry.kid, "x": x,
+            });
+            (Algorithm::EdDSA, encoding, decoding, jwk)
+        }
+        other => return Err(format!("密钥 {} 使用了不支持的算法: {}", entry.kid, other)),
+    };
+
+    Ok(SigningKey { kid: entry.kid, alg, status: entry.status, encoding, decoding, jwk })
+}
@@ -0,0 +1,112 @@
+// src/mailer.rs
+// 邮件发送抽象：生产环境使用 SMTP，本地开发和测试使用文件/日志
+use axum::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), String>;
+}
+
+/// 根据 MAIL_TRANSPORT 选择实现: smtp | file (默认)
+pub fn from_env() -> Result<Arc<dyn Mailer>, String> {
+    match std::env::var("MAIL_TRANSPORT").as_deref() {
+        Ok("smtp") => Ok(Arc::new(SmtpMailer::from_env()?)),
+        Ok("file") | Err(_) => Ok(Arc::new(FileMailer::new(
+            std::env::var("MAIL_DIR").ok().map(PathBuf::from),
+        ))),
+        Ok(other) => Err(format!("不支持的 MAIL_TRANSPORT: {}", other)),
+    }
+}
+
+// --- SMTP 实现 ---
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    /// 读取 SMTP_HOST / SMTP_PORT / SMTP_USERNAME / SMTP_PASSWORD / SMTP_TLS / MAIL_FROM
+    pub fn from_env() -> Result<Self, String> {
+        let host = std::env::var("SMTP_HOST").map_err(|_| "SMTP_HOST must be set")?;
+        let from = std::env::var("MAIL_FROM")
+            .map_err(|_| "MAIL_FROM must be set")?
+            .parse::<Mailbox>()
+            .map_err(|e| format!("MAIL_FROM 格式错误: {}", e))?;
+
+        // starttls (默认) | tls | none (仅限本地调试)
+        let mut builder = match std::env::var("SMTP_TLS").as_deref() {
+            Ok("tls") => AsyncSmtpTransport::<Tokio1Executor>::relay(&host).map_err(|e| e.to_string())?,
+            Ok("none") => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host),
+            _ => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host).map_err(|e| e.to_string())?,
+        };
+        if let Ok(port) = std::env::var("SMTP_PORT") {
+            builder = builder.port(port.parse().map_err(|_| "SMTP_PORT 格式错误")?);
+        }
+        if let (Ok(user), Ok(pass)) = (std::env::var("SMTP_USERNAME"), std::env::var("SMTP_PASSWORD")) {
+            builder = builder.credentials(Credentials::new(user, pass));
+        }
+
+        Ok(SmtpMailer { transport: builder.build(), from })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), String> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse::<Mailbox>().map_err(|e| e.to_string())?)
+            .subject(email.subject)
+            .body(email.body)
+            .map_err(|e| e.to_string())?;
+
+        self.transport.send(message).await.map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+// --- 文件/日志实现 (本地开发与测试) ---
+
+/// 将邮件写入日志；配置了目录时同时保存为文本文件，便于测试读取其中的链接
+pub struct FileMailer {
+    dir: Option<PathBuf>,
+}
+
+impl FileMailer {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        FileMailer { dir }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), String> {
+        tracing::info!("📧 邮件 -> {} [{}]\n{}", email.to, email.subject, email.body);
+
+        if let Some(dir) = &self.dir {
+            tokio::fs::create_dir_all(dir).await.map_err(|e| e.to_string())?;
+            let file = dir.join(format!(
+                "{}-{}.txt",
+                chrono::Utc::now().format("%Y%m%dT%H%M%S%.6f"),
+                email.to.replace(['/', '\\'], "_")
+            ));
+            let content = format!("To: {}\nSubject: {}\n\n{}\n", email.to, email.subject, email.body);
+            tokio::fs::write(file, content).await.map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
@@ -1,20 +1,34 @@
 // src/main.rs
 use axum::{
     http::Method,
-    routing::{delete, get, patch, post},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tower_http::cors::{Any, CorsLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod access;
 mod auth;
+mod calendar;
+mod config;
 mod error;
+mod graph;
 mod handlers;
+mod jobs;
+mod keys;
+mod mailer;
 mod models;
+mod oidc;
+mod rank;
+mod revocation;
+mod throttle;
+mod totp;
 mod validation;
+mod workflow;
 
 pub use error::AppError;
 
@@ -23,6 +37,13 @@ use handlers::*;
 #[derive(Clone)]
 pub struct AppState {
     pub db: PgPool,
+    pub config: Arc<config::Config>,
+    pub mailer: Arc<dyn mailer::Mailer>,
+    pub keys: Arc<keys::KeyManager>,
+    /// 未配置 OIDC_ISSUER 时为 None
+    pub oidc: Option<Arc<oidc::OidcClient>>,
+    pub revocation: Arc<revocation::TokenRevocation>,
+    pub throttle: Arc<throttle::LoginThrottle>,
 }
 
 #[tokio::main]
@@ -38,6 +59,17 @@ async fn main() {
     dotenvy::dotenv().ok();
     let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set in .env");
 
+    // 密钥缺失或无效时直接启动失败，绝不回退到硬编码的密钥
+    let keys = keys::KeyManager::from_env().unwrap_or_else(|e| panic!("加载 JWT 签名密钥失败: {}", e));
+    let config = config::Config::from_env().unwrap_or_else(|e| panic!("加载配置失败: {}", e));
+    let mailer = mailer::from_env().unwrap_or_else(|e| panic!("初始化邮件发送失败: {}", e));
+    let oidc = oidc::OidcConfig::from_env(&config.app_base_url)
+        .unwrap_or_else(|e| panic!("加载 OIDC 配置失败: {}", e))
+        .map(|c| Arc::new(oidc::OidcClient::new(c)));
+
+    // 预先生成占位哈希，避免第一次登录不存在的用户时耗时异常
+    auth::dummy_password_hash(&config.argon2);
+
     let pool = PgPoolOptions::new()
         .max_connections(5)
         .connect(&db_url)
@@ -46,32 +78,122 @@ async fn main() {
 
     tracing::info!("✅ 数据库连接成功!");
 
-    let state = AppState { db: pool };
+    jobs::spawn_account_purge(pool.clone());
+    jobs::spawn_rank_rebalance(pool.clone());
+
+    let state = AppState {
+        db: pool,
+        config: Arc::new(config),
+        mailer,
+        keys: Arc::new(keys),
+        oidc,
+        revocation: Arc::new(revocation::TokenRevocation::default()),
+        throttle: Arc::new(throttle::LoginThrottle::default()),
+    };
 
     let cors = CorsLayer::new()
         .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
         .allow_headers(Any);
 
     let app = Router::new()
+        // 公钥发布 (其他服务据此验证 Access Token)
+        .route("/.well-known/jwks.json", get(jwks_handler))
         // 认证
         .route("/api/register", post(register_handler))
         .route("/api/login", post(login_handler))
+        .route("/api/login/2fa", post(login_2fa_handler))
         .route("/api/refresh", post(refresh_handler))
+        .route("/api/oidc/authorize", get(oidc_authorize_handler))
+        .route("/api/oidc/callback", post(oidc_callback_handler))
+        .route("/api/logout", post(logout_handler))
+        .route("/api/logout-all", post(logout_all_handler))
+        .route("/api/email/verify", post(verify_email_handler))
+        .route("/api/email/verification/resend", post(resend_verification_handler))
+        .route("/api/account/restore", post(restore_account_handler))
+        .route("/api/password/forgot", post(forgot_password_handler))
+        .route("/api/password/reset", post(reset_password_handler))
+        // 当前用户
+        .route("/api/me", get(get_me_handler))
+        .route("/api/me", patch(update_me_handler))
+        .route("/api/me", delete(delete_me_handler))
+        .route("/api/me/export", get(export_me_handler))
+        .route("/api/me/password", post(change_password_handler))
+        .route("/api/me/2fa/setup", post(setup_2fa_handler))
+        .route("/api/me/2fa/confirm", post(confirm_2fa_handler))
+        .route("/api/me/2fa/disable", post(disable_2fa_handler))
+        .route("/api/me/identities", get(get_identities_handler))
+        .route("/api/me/identities/oidc", post(link_oidc_handler))
+        .route("/api/me/identities/:id", delete(delete_identity_handler))
+        .route("/api/me/invites", get(get_invites_handler))
+        .route("/api/me/invites", post(create_invite_handler))
+        .route("/api/me/invites/:id", delete(revoke_invite_handler))
+        .route("/api/me/issues", get(get_assigned_issues_handler))
+        .route("/api/me/project-transfers", get(get_incoming_transfers_handler))
+        .route("/api/me/tokens", get(get_personal_tokens_handler))
+        .route("/api/me/tokens", post(create_personal_token_handler))
+        .route("/api/me/tokens/:id", delete(delete_personal_token_handler))
+        // 管理后台 (仅管理员)
+        .route("/api/admin/stats", get(admin_stats_handler))
+        .route("/api/admin/users", get(admin_list_users_handler))
+        .route("/api/admin/users/:id", get(admin_get_user_handler))
+        .route("/api/admin/users/:id/disable", post(admin_disable_user_handler))
+        .route("/api/admin/users/:id/enable", post(admin_enable_user_handler))
+        .route("/api/admin/users/:id/logout", post(admin_logout_user_handler))
+        .route("/api/admin/users/:id/2fa/reset", post(admin_reset_2fa_handler))
+        .route("/api/admin/users/:id/role", patch(admin_update_role_handler))
+        // 会话管理
+        .route("/api/sessions", get(get_sessions_handler))
+        .route("/api/sessions/:id", delete(delete_session_handler))
+        // 工作区路由
+        .route("/api/workspaces", get(get_workspaces_handler))
+        .route("/api/workspaces", post(create_workspace_handler))
+        .route("/api/workspaces/:id", get(get_workspace_handler))
+        .route("/api/workspaces/:id", patch(update_workspace_handler))
+        .route("/api/workspaces/:id", delete(delete_workspace_handler))
+        .route("/api/workspaces/:id/members", get(get_workspace_members_handler))
+        .route("/api/workspaces/:id/members", post(add_workspace_member_handler))
+        .route("/api/workspaces/:id/members/:user_id", patch(update_workspace_member_handler))
+        .route("/api/workspaces/:id/members/:user_id", delete(remove_workspace_member_handler))
         // 项目路由
         .route("/api/projects", get(get_projects_handler))
         .route("/api/projects", post(create_project_handler))
         .route("/api/projects/:id", patch(update_project_handler))
         .route("/api/projects/:id", delete(delete_project_handler))
+        .route("/api/projects/:id/members", get(get_project_members_handler))
+        .route("/api/projects/:id/members", post(add_project_member_handler))
+        .route("/api/projects/:id/members/:user_id", patch(update_project_member_handler))
+        .route("/api/projects/:id/members/:user_id", delete(remove_project_member_handler))
+        .route("/api/projects/:id/transfer", post(create_project_transfer_handler))
+        .route("/api/projects/:id/transfer", delete(cancel_project_transfer_handler))
+        .route("/api/projects/:id/transfers", get(get_project_transfers_handler))
+        .route("/api/projects/:id/graph", get(get_project_graph_handler))
+        .route("/api/projects/:id/workflow", get(get_project_workflow_handler))
+        .route("/api/projects/:id/workflow", put(update_project_workflow_handler))
+        .route("/api/project-transfers/:id/accept", post(accept_project_transfer_handler))
+        .route("/api/project-transfers/:id/decline", post(decline_project_transfer_handler))
         // 任务路由
         .route("/api/issues", get(get_all_my_issues_handler))
         .route("/api/projects/:id/issues", get(get_project_issues_handler))
+        .route("/api/projects/:id/board", get(get_project_board_handler))
         .route("/api/search", get(unified_search_handler))
         .route("/api/issues", post(create_issue_handler))
         .route("/api/issues/:id", patch(update_issue_handler))
         .route("/api/issues/:id", delete(delete_issue_handler))
+        .route("/api/issues/:id/children", get(get_issue_children_handler))
+        .route("/api/issues/:id/children", post(create_child_issue_handler))
+        .route("/api/issues/:id/parent", patch(set_issue_parent_handler))
+        .route("/api/issues/:id/move", post(move_issue_handler))
+        .route("/api/issues/:id/relations", get(get_issue_relations_handler))
+        .route("/api/issues/:id/relations", post(create_issue_relation_handler))
+        .route("/api/issues/:id/relations/:relation_id", delete(delete_issue_relation_handler))
         .route("/api/issues/:id/comments", get(get_issue_comments_handler))
         .route("/api/issues/:id/comments", post(create_comment_handler))
+        // 标签路由
+        .route("/api/projects/:id/labels", get(get_project_labels_handler))
+        .route("/api/projects/:id/labels", post(create_label_handler))
+        .route("/api/labels/:id", patch(update_label_handler))
+        .route("/api/labels/:id", delete(delete_label_handler))
         .with_state(state)
         .layer(cors);
 
@@ -79,5 +201,7 @@ async fn main() {
     tracing::info!("🚀 服务器运行在: {}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .unwrap();
 }
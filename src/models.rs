@@ -1,17 +1,107 @@
 // src/models.rs
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use sqlx::FromRow;
 use validator::Validate;
 
+use crate::access::{ProjectRole, WorkspaceRole};
+use crate::auth::Role;
+
+// --- 0. Workspace 模型 ---
+#[derive(Debug, FromRow, Serialize)]
+pub struct Workspace {
+    pub id: i32,
+    pub name: String,
+    /// 个人工作区所属用户，团队工作区为 null
+    pub personal_user_id: Option<i32>,
+    pub timezone: String,
+    /// 新项目的默认工作流状态，第一个为初始状态
+    pub default_statuses: Vec<String>,
+    /// 新项目的默认标签
+    pub default_labels: Vec<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// 工作区列表项 (附带当前用户的角色)
+#[derive(Debug, FromRow, Serialize)]
+pub struct MyWorkspace {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub workspace: Workspace,
+    pub role: WorkspaceRole,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateWorkspaceSchema {
+    #[validate(length(min = 1, max = 100, message = "工作区名称不能为空"))]
+    pub name: String,
+    #[validate(custom(function = "crate::validation::validate_timezone"))]
+    pub timezone: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateWorkspaceSchema {
+    #[validate(length(min = 1, max = 100, message = "工作区名称不能为空"))]
+    pub name: Option<String>,
+    #[validate(custom(function = "crate::validation::validate_timezone"))]
+    pub timezone: Option<String>,
+    #[validate(
+        length(min = 2, max = 20, message = "默认状态需要 2-20 个 (初始状态之外至少还要有一个完成状态)"),
+        custom(function = "crate::validation::validate_name_list")
+    )]
+    pub default_statuses: Option<Vec<String>>,
+    #[validate(
+        length(max = 50, message = "默认标签最多 50 个"),
+        custom(function = "crate::validation::validate_label_list")
+    )]
+    pub default_labels: Option<Vec<String>>,
+}
+
+/// 工作区成员 (带用户资料)
+#[derive(Debug, FromRow, Serialize)]
+pub struct WorkspaceMember {
+    pub user_id: i32,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub role: WorkspaceRole,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AddWorkspaceMemberSchema {
+    #[validate(length(min = 1, message = "用户名不能为空"))]
+    pub username: String,
+    pub role: WorkspaceRole,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateWorkspaceMemberSchema {
+    pub role: WorkspaceRole,
+}
+
 // --- 1. Project 模型 ---
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum ProjectStatus {
+    Backlog,
+    Active,
+    Completed,
+    Paused,
+    Canceled,
+}
+
 #[derive(Debug, FromRow, Serialize)]
 pub struct Project {
     pub id: i32,
-    pub user_id: i32,
+    pub workspace_id: i32,
+    /// 项目所有者
+    pub user_id: Option<i32>,
     pub name: String,
     pub description: Option<String>,
-    pub status: String,
+    pub status: ProjectStatus,
     pub color: Option<String>,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
@@ -23,26 +113,191 @@ pub struct CreateProjectSchema {
     pub name: String,
     pub description: Option<String>,
     pub color: Option<String>,
+    /// 所属工作区，默认取 X-Workspace-Id 请求头，都未指定时为个人工作区
+    pub workspace_id: Option<i32>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct UpdateProjectSchema {
     pub name: Option<String>,
     pub description: Option<String>,
-    pub status: Option<String>,
+    pub status: Option<ProjectStatus>,
     pub color: Option<String>,
 }
 
-// --- 2. Issue 模型 (由原 Plan 升级) ---
+/// 项目成员 (带用户资料)
+#[derive(Debug, FromRow, Serialize)]
+pub struct ProjectMember {
+    pub user_id: i32,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub role: ProjectRole,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// 当前用户在某个项目中的成员身份 (导出数据使用)
+#[derive(Debug, FromRow, Serialize)]
+pub struct ProjectMembership {
+    pub project_id: i32,
+    pub project_name: String,
+    pub workspace_id: i32,
+    pub role: ProjectRole,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AddProjectMemberSchema {
+    #[validate(length(min = 1, message = "用户名不能为空"))]
+    pub username: String,
+    pub role: ProjectRole,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateProjectMemberSchema {
+    pub role: ProjectRole,
+}
+
+/// 项目所有权转移状态；过期的待确认请求显示为 expired，发起新请求时落库为 expired
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+pub enum TransferStatus {
+    Pending,
+    Accepted,
+    Declined,
+    Canceled,
+    Expired,
+}
+
 #[derive(Debug, FromRow, Serialize)]
+pub struct ProjectTransfer {
+    pub id: i32,
+    pub project_id: i32,
+    pub project_name: String,
+    pub from_user_id: Option<i32>,
+    pub from_username: Option<String>,
+    pub to_user_id: i32,
+    pub to_username: String,
+    pub requested_by: Option<i32>,
+    pub status: TransferStatus,
+    pub from_workspace_id: Option<i32>,
+    pub to_workspace_id: Option<i32>,
+    pub expires_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateProjectTransferSchema {
+    /// 接收人用户名，必须已能访问该项目
+    #[validate(length(min = 1, message = "用户名不能为空"))]
+    pub username: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AcceptProjectTransferSchema {
+    /// 接受后项目所在的工作区；未指定时，原所有者个人工作区中的项目迁入接收人的个人工作区，其他保持不变
+    pub workspace_id: Option<i32>,
+}
+
+// --- 1.1 工作流模型 ---
+/// 工作流状态分类，子任务进度、阻塞判断等按分类计算
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+pub enum StatusCategory {
+    Backlog,
+    Unstarted,
+    Started,
+    Completed,
+    Canceled,
+}
+
+impl StatusCategory {
+    /// 已完成或已取消
+    pub fn is_closed(&self) -> bool {
+        matches!(self, StatusCategory::Completed | StatusCategory::Canceled)
+    }
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct WorkflowState {
+    pub id: i32,
+    pub name: String,
+    pub category: StatusCategory,
+    pub position: i32,
+    /// 看板中该列的在制品 (WIP) 上限，null 表示不限制
+    pub wip_limit: Option<i32>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// 允许的状态流转 (状态名称)
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub struct WorkflowTransition {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectWorkflow {
+    /// 按顺序排列，第一个为新任务的初始状态
+    pub states: Vec<WorkflowState>,
+    /// 为空时任意状态之间都可以流转
+    pub transitions: Vec<WorkflowTransition>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkflowStateInput {
+    /// 已有状态的 ID (用于改名)；未指定时按名称匹配已有状态，匹配不到则新建
+    pub id: Option<i32>,
+    pub name: String,
+    pub category: StatusCategory,
+    /// 看板 WIP 上限 (1-1000)，未指定时不限制
+    pub wip_limit: Option<i32>,
+}
+
+/// 整体替换项目工作流；未出现的已有状态会被删除 (仍有任务使用时拒绝)
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateWorkflowSchema {
+    #[validate(length(min = 2, max = 20, message = "工作流需要 2-20 个状态"))]
+    pub states: Vec<WorkflowStateInput>,
+    /// 未指定或为空时不限制流转
+    #[validate(length(max = 400, message = "流转规则最多 400 条"))]
+    pub transitions: Option<Vec<WorkflowTransition>>,
+}
+
+// --- 2. Issue 模型 (由原 Plan 升级) ---
+#[derive(Debug, Clone, FromRow, Serialize)]
 pub struct Issue {
     pub id: i32,
     pub project_id: i32,
-    pub user_id: i32,
+    /// 创建者，创建者注销后为 null
+    pub creator_id: Option<i32>,
+    /// 负责人 ID 列表
+    pub assignee_ids: Vec<i32>,
+    /// 标签 ID 列表
+    pub label_ids: Vec<i32>,
+    /// 父任务，顶层任务为 null
+    pub parent_id: Option<i32>,
+    /// 估算工作量 (点数)
+    pub estimate: Option<i32>,
+    /// 直接子任务数 (不含已取消)
+    pub child_count: i64,
+    /// 已完成的直接子任务数
+    pub completed_child_count: i64,
+    /// 按估算加权的子任务完成度 (0-1，未估算的子任务按 1 计)，没有子任务时为 null
+    pub progress: Option<f64>,
+    /// 是否被未完成的任务阻塞
+    pub blocked: bool,
     pub title: String,
     pub description: Option<String>,
     pub status: String,
+    /// 当前状态在项目工作流中的分类
+    pub status_category: StatusCategory,
     pub priority: i32,
+    /// 手动排序键 (项目内按字节序排列)
+    pub rank: String,
     pub due_date: Option<DateTime<Utc>>,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
@@ -50,13 +305,24 @@ pub struct Issue {
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct CreateIssueSchema {
-    pub project_id: i32, // 必须指定所属项目
+    /// 所属项目；指定父任务时可省略 (取父任务所在项目)
+    pub project_id: Option<i32>,
+    /// 父任务 (必须属于同一项目)
+    pub parent_id: Option<i32>,
     #[validate(length(min = 1, max = 255, message = "标题不能为空"))]
     pub title: String,
     #[validate(length(min = 5, message = "描述内容至少需要 5 个字"))]
     pub description: Option<String>,
+    /// 初始状态，未指定时取项目工作流的第一个状态
+    pub status: Option<String>,
     pub priority: Option<i32>,
     pub due_date: Option<DateTime<Utc>>,
+    #[validate(range(min = 0, max = 1000, message = "估算需在 0-1000 之间"))]
+    pub estimate: Option<i32>,
+    #[validate(length(max = 10, message = "负责人最多 10 个"))]
+    pub assignee_ids: Option<Vec<i32>>,
+    #[validate(length(max = 20, message = "标签最多 20 个"))]
+    pub label_ids: Option<Vec<i32>>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -68,6 +334,214 @@ pub struct UpdateIssueSchema {
     pub status: Option<String>,
     pub priority: Option<i32>,
     pub due_date: Option<DateTime<Utc>>,
+    #[validate(range(min = 0, max = 1000, message = "估算需在 0-1000 之间"))]
+    pub estimate: Option<i32>,
+    /// 整体替换负责人列表，传空数组清空
+    #[validate(length(max = 10, message = "负责人最多 10 个"))]
+    pub assignee_ids: Option<Vec<i32>>,
+    /// 整体替换标签列表，传空数组清空
+    #[validate(length(max = 20, message = "标签最多 20 个"))]
+    pub label_ids: Option<Vec<i32>>,
+}
+
+/// 调整父任务，parent_id 为 null 时变为顶层任务
+#[derive(Debug, Deserialize, Validate)]
+pub struct SetParentSchema {
+    pub parent_id: Option<i32>,
+}
+
+/// 删除带子任务的任务时如何处理子任务
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChildrenOnDelete {
+    /// 连同所有后代一并删除
+    Cascade,
+    /// 子任务提升到被删除任务的上一级
+    Promote,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteIssueQuery {
+    pub children: Option<ChildrenOnDelete>,
+}
+
+/// 看板拖拽排序：after / before 为移动后紧邻的前一个 / 后一个任务，可只指定一个
+/// 都不指定时移到项目末尾
+#[derive(Debug, Deserialize, Validate)]
+pub struct MoveIssueSchema {
+    pub after: Option<i32>,
+    pub before: Option<i32>,
+    /// 同时变更状态 (如拖到看板的另一列)
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateIssueQuery {
+    /// 任务仍被阻塞时强制设为已完成分类的状态
+    #[serde(default)]
+    pub force: bool,
+}
+
+// --- 2.1 任务关系与依赖图 ---
+
+/// 存储的关系类型 (方向: source -> target)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+pub enum RelationType {
+    Blocks,
+    Duplicates,
+    RelatesTo,
+}
+
+/// 从某个任务的视角看到的关系
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+pub enum RelationKind {
+    Blocks,
+    BlockedBy,
+    Duplicates,
+    DuplicatedBy,
+    RelatesTo,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct IssueRelation {
+    pub id: i32,
+    #[serde(rename = "type")]
+    pub kind: RelationKind,
+    /// 关系另一端的任务
+    pub issue_id: i32,
+    pub issue_title: String,
+    pub issue_status: String,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateRelationSchema {
+    #[serde(rename = "type")]
+    pub kind: RelationKind,
+    /// 关系另一端的任务 (必须属于同一项目)
+    pub issue_id: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IssueGraphNode {
+    pub id: i32,
+    pub title: String,
+    pub status: String,
+    pub status_category: StatusCategory,
+    pub due_date: Option<DateTime<Utc>>,
+    pub blocked: bool,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct IssueGraphEdge {
+    pub id: i32,
+    pub source_issue_id: i32,
+    pub target_issue_id: i32,
+    #[serde(rename = "type")]
+    pub relation_type: RelationType,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectGraph {
+    pub nodes: Vec<IssueGraphNode>,
+    pub edges: Vec<IssueGraphEdge>,
+    /// 按阻塞关系的执行顺序 (同时可执行的按截止日期先后)
+    pub topological_order: Vec<i32>,
+    /// 决定未完成任务最晚完成时间的阻塞链 (按截止日期推算预计完成时间)
+    pub critical_path: Vec<i32>,
+    /// 阻塞方截止日期晚于被阻塞方的关系 ID (排期冲突)
+    pub due_date_conflicts: Vec<i32>,
+}
+
+// --- 2.2 Label 模型 ---
+#[derive(Debug, FromRow, Serialize)]
+pub struct Label {
+    pub id: i32,
+    pub project_id: i32,
+    pub name: String,
+    pub color: String,
+    pub description: Option<String>,
+    /// 使用该标签的任务数
+    pub issue_count: i64,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateLabelSchema {
+    #[validate(
+        length(min = 1, max = 50, message = "标签名称需为 1-50 个字符"),
+        custom(function = "crate::validation::validate_label_name")
+    )]
+    pub name: String,
+    #[validate(custom(function = "crate::validation::validate_hex_color"))]
+    pub color: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateLabelSchema {
+    #[validate(
+        length(min = 1, max = 50, message = "标签名称需为 1-50 个字符"),
+        custom(function = "crate::validation::validate_label_name")
+    )]
+    pub name: Option<String>,
+    #[validate(custom(function = "crate::validation::validate_hex_color"))]
+    pub color: Option<String>,
+    /// 传 null 清空描述
+    #[serde(default, deserialize_with = "double_option")]
+    pub description: Option<Option<String>>,
+}
+
+// --- 2.3 看板模型 ---
+/// 看板分列方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoardGroupBy {
+    /// 按工作流状态，WIP 上限取自工作流配置
+    #[default]
+    Status,
+    /// 按负责人，多人负责的任务出现在每个负责人的列中
+    Assignee,
+    Priority,
+    /// 按标签，多个标签的任务出现在每个标签的列中
+    Label,
+}
+
+/// 看板参数；筛选与排序参数同任务列表 (IssueQuery)，默认按手动排序
+#[derive(Debug, Deserialize)]
+pub struct BoardQuery {
+    #[serde(default)]
+    pub group_by: BoardGroupBy,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BoardColumn {
+    /// 列标识: 状态名称 / 用户 ID / 优先级 / 标签 ID，"none" 表示未指派或无标签
+    pub key: String,
+    pub title: String,
+    /// 标签颜色 (仅按标签分列)
+    pub color: Option<String>,
+    /// 状态分类 (仅按状态分列)
+    pub category: Option<StatusCategory>,
+    pub wip_limit: Option<i32>,
+    /// 该状态下的全部任务数 (不受筛选条件影响) 超过 WIP 上限
+    pub wip_exceeded: bool,
+    /// 列中的卡片数 (筛选后)
+    pub count: usize,
+    pub issues: Vec<Issue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Board {
+    pub group_by: BoardGroupBy,
+    /// 筛选后的任务数 (同一任务出现在多列时只计一次)
+    pub total: usize,
+    pub columns: Vec<BoardColumn>,
 }
 
 // --- 3. 认证与查询模型 ---
@@ -77,15 +551,95 @@ pub struct User {
     pub username: String,
     #[serde(skip)]
     pub password_hash: String,
+    pub email: Option<String>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub role: Role,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub timezone: String,
+    pub locale: String,
+    pub week_start_day: i16,
+    pub default_project_id: Option<i32>,
+    #[serde(skip)]
+    pub token_version: i32,
+    #[serde(skip)]
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub disabled_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub deletion_scheduled_at: Option<DateTime<Utc>>,
+    /// 被管理员停用的时间
+    #[serde(skip)]
+    pub suspended_at: Option<DateTime<Utc>>,
     pub created_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct RegisterSchema {
-    #[validate(length(min = 3, max = 20, message = "用户名长度需在 3-20 位之间"))]
+    #[validate(
+        length(min = 3, max = 20, message = "用户名长度需在 3-20 位之间"),
+        custom(function = "crate::validation::validate_username")
+    )]
     pub username: String,
     #[validate(length(min = 6, message = "密码至少需要 6 位"))]
     pub password: String,
+    /// 服务器要求验证邮箱时必填
+    #[validate(email(message = "邮箱格式不正确"))]
+    pub email: Option<String>,
+    /// 邀请制注册时必填
+    pub invite_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct DeleteAccountSchema {
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct VerifyEmailSchema {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResendVerificationSchema {
+    #[validate(email(message = "邮箱格式不正确"))]
+    pub email: String,
+}
+
+/// PATCH /api/me: 只修改传入的字段；可为空的字段传 null 表示清空
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateProfileSchema {
+    #[validate(
+        length(min = 3, max = 20, message = "用户名长度需在 3-20 位之间"),
+        custom(function = "crate::validation::validate_username")
+    )]
+    pub username: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
+    #[validate(length(min = 1, max = 100, message = "显示名称长度需在 1-100 位之间"))]
+    pub display_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    #[validate(email(message = "邮箱格式不正确"))]
+    pub email: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    #[validate(custom(function = "crate::validation::validate_http_url"))]
+    pub avatar_url: Option<Option<String>>,
+    #[validate(custom(function = "crate::validation::validate_timezone"))]
+    pub timezone: Option<String>,
+    #[validate(custom(function = "crate::validation::validate_locale"))]
+    pub locale: Option<String>,
+    #[validate(range(min = 0, max = 6, message = "每周第一天需在 0 (周日) - 6 (周六) 之间"))]
+    pub week_start_day: Option<i16>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub default_project_id: Option<Option<i32>>,
+}
+
+/// 区分 "未传" (None) 与 "传了 null" (Some(None))
+fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -94,6 +648,26 @@ pub struct LoginSchema {
     pub password: String,
 }
 
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangePasswordSchema {
+    pub old_password: String,
+    #[validate(length(min = 6, message = "密码至少需要 6 位"))]
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ForgotPasswordSchema {
+    #[validate(email(message = "邮箱格式不正确"))]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResetPasswordSchema {
+    pub token: String,
+    #[validate(length(min = 6, message = "密码至少需要 6 位"))]
+    pub new_password: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct AuthResponse {
     pub token: String,
@@ -101,10 +675,151 @@ pub struct AuthResponse {
     pub username: String,
 }
 
+/// 开启了两步验证的用户密码正确后返回，需携带 mfa_token 和验证码调用 /api/login/2fa
+#[derive(Debug, Serialize)]
+pub struct MfaChallenge {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub expires_in: i64, // 秒
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Authenticated(AuthResponse),
+    MfaRequired(MfaChallenge),
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct MfaLoginSchema {
+    pub mfa_token: String,
+    /// 6 位 TOTP 验证码或一次性恢复码
+    #[validate(length(min = 1, message = "验证码不能为空"))]
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorSetupResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct TwoFactorCodeSchema {
+    #[validate(length(min = 1, message = "验证码不能为空"))]
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct DisableTwoFactorSchema {
+    pub password: String,
+    #[validate(length(min = 1, message = "验证码不能为空"))]
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct IssueQuery {
     pub q: Option<String>,
     pub status: Option<String>,
+    pub assignee: Option<UserFilter>,
+    pub creator: Option<UserFilter>,
+    /// 标签名，多个用逗号分隔 (需同时带有所有标签)，不区分大小写
+    pub label: Option<String>,
+    /// 排序方式，未指定时各列表接口使用各自的默认排序
+    pub sort: Option<IssueSort>,
+    /// 按截止日期筛选，"今天""本周"按当前用户的时区与每周第一天计算
+    pub due: Option<DueFilter>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DueFilter {
+    /// 截止日期在今天之前且未完成/取消
+    Overdue,
+    Today,
+    Week,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueSort {
+    /// 手动排序 (看板拖拽的顺序)
+    Manual,
+    Priority,
+    DueDate,
+    Created,
+    Updated,
+}
+
+impl IssueSort {
+    /// 对应的 ORDER BY 子句 (任务表别名为 i)
+    pub fn order_by(&self) -> &'static str {
+        match self {
+            IssueSort::Manual => "i.project_id, i.rank, i.id",
+            IssueSort::Priority => "i.priority DESC, i.created_at DESC",
+            IssueSort::DueDate => "i.due_date ASC NULLS LAST, i.priority DESC",
+            IssueSort::Created => "i.created_at DESC",
+            IssueSort::Updated => "i.updated_at DESC",
+        }
+    }
+}
+
+impl IssueQuery {
+    /// 解析 label 参数为去重后的小写标签名列表，未指定时为 None
+    pub fn label_names(&self) -> Option<Vec<String>> {
+        let mut names: Vec<String> = self
+            .label
+            .as_deref()?
+            .split(',')
+            .map(|n| n.trim().to_lowercase())
+            .filter(|n| !n.is_empty())
+            .collect();
+        names.sort();
+        names.dedup();
+        (!names.is_empty()).then_some(names)
+    }
+}
+
+/// 按用户筛选任务: `me` (当前用户)、`none` (无) 或用户 ID
+#[derive(Debug, Clone, Copy)]
+pub enum UserFilter {
+    Me,
+    Nobody,
+    User(i32),
+}
+
+impl UserFilter {
+    /// 转换为 (用户 ID, 是否筛选"无")，便于绑定到 SQL
+    pub fn resolve(filter: Option<UserFilter>, me: i32) -> (Option<i32>, bool) {
+        match filter {
+            None => (None, false),
+            Some(UserFilter::Me) => (Some(me), false),
+            Some(UserFilter::Nobody) => (None, true),
+            Some(UserFilter::User(id)) => (Some(id), false),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for UserFilter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        match value.as_str() {
+            "me" => Ok(UserFilter::Me),
+            "none" => Ok(UserFilter::Nobody),
+            id => id
+                .parse()
+                .map(UserFilter::User)
+                .map_err(|_| serde::de::Error::custom("用户筛选只能是 me、none 或用户 ID")),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -112,6 +827,145 @@ pub struct RefreshRequest {
     pub refresh_token: String,
 }
 
+/// 个人访问令牌 (不含令牌本身，只展示前缀便于辨认)
+#[derive(Debug, FromRow, Serialize)]
+pub struct PersonalAccessToken {
+    pub id: i32,
+    pub name: String,
+    pub token_prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreatePersonalTokenSchema {
+    #[validate(length(min = 1, max = 100, message = "令牌名称不能为空"))]
+    pub name: String,
+    #[validate(length(min = 1, message = "至少需要一个权限"))]
+    pub scopes: Vec<crate::auth::Scope>,
+    /// 有效天数，不填表示永不过期
+    #[validate(range(min = 1, max = 3650, message = "有效期需在 1-3650 天之间"))]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedPersonalToken {
+    /// 令牌明文，只返回这一次
+    pub token: String,
+    #[serde(flatten)]
+    pub info: PersonalAccessToken,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct InviteCode {
+    pub id: i32,
+    pub code_prefix: String,
+    pub max_uses: i32,
+    pub use_count: i32,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateInviteSchema {
+    /// 可使用次数，默认 1 (只有管理员可以设置大于 1)
+    #[validate(range(min = 1, max = 1000, message = "使用次数需在 1-1000 之间"))]
+    pub max_uses: Option<i32>,
+    /// 有效天数，默认 7 天
+    #[validate(range(min = 1, max = 365, message = "有效期需在 1-365 天之间"))]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedInvite {
+    /// 邀请码明文，只返回这一次
+    pub code: String,
+    #[serde(flatten)]
+    pub info: InviteCode,
+}
+
+/// GET /api/oidc/authorize 的查询参数
+#[derive(Debug, Deserialize)]
+pub struct OidcAuthorizeQuery {
+    /// 邀请制下首次登录需要；在跳转前校验，避免完成授权后才发现邀请码无效
+    pub invite_code: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OidcAuthorizeResponse {
+    pub authorization_url: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct OidcCallbackSchema {
+    #[validate(length(min = 1, message = "缺少授权码"))]
+    pub code: String,
+    #[validate(length(min = 1, message = "缺少 state"))]
+    pub state: String,
+    /// 邀请制下首次登录 (需要创建账户) 时必填，也可以在 /api/oidc/authorize 时提供
+    pub invite_code: Option<String>,
+}
+
+/// 关联到本地用户的第三方身份
+#[derive(Debug, FromRow, Serialize)]
+pub struct UserIdentity {
+    pub id: i32,
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+/// 登录会话 (一个刷新令牌家族对应一台设备)
+#[derive(Debug, FromRow, Serialize)]
+pub struct Session {
+    pub id: uuid::Uuid,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub current: bool,
+}
+
+/// 导出数据中的会话记录 (包含已结束的会话)
+#[derive(Debug, FromRow, Serialize)]
+pub struct SessionRecord {
+    pub id: uuid::Uuid,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// GET /api/me/export 的响应
+#[derive(Debug, Serialize)]
+pub struct AccountExport {
+    pub exported_at: DateTime<Utc>,
+    pub user: User,
+    /// 所在的工作区及角色
+    pub workspaces: Vec<MyWorkspace>,
+    /// 项目成员身份及角色
+    pub project_memberships: Vec<ProjectMembership>,
+    /// 自己拥有的项目
+    pub projects: Vec<Project>,
+    /// 可以访问但不属于自己的项目 (只含项目信息，不含其中他人的任务)
+    pub member_projects: Vec<Project>,
+    /// 发起或收到的项目转移
+    pub transfers: Vec<ProjectTransfer>,
+    pub issues: Vec<Issue>,
+    pub comments: Vec<Comment>,
+    pub sessions: Vec<SessionRecord>,
+    pub identities: Vec<UserIdentity>,
+    pub personal_access_tokens: Vec<PersonalAccessToken>,
+}
+
 #[derive(Debug, sqlx::FromRow, serde::Serialize)]
 pub struct Comment {
     pub id: i32,
@@ -136,4 +990,71 @@ pub struct UnifiedSearchResult {
     pub description: Option<String>,
     pub status: String,
     pub color: Option<String>, // 仅项目有颜色
-}
\ No newline at end of file
+}
+// --- 管理后台模型 ---
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountStatus {
+    Active,
+    Disabled,
+    PendingDeletion,
+}
+
+impl AccountStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountStatus::Active => "active",
+            AccountStatus::Disabled => "disabled",
+            AccountStatus::PendingDeletion => "pending_deletion",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminUserQuery {
+    pub q: Option<String>, // 匹配用户名、邮箱、显示名称
+    pub role: Option<Role>,
+    pub status: Option<AccountStatus>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// 管理后台看到的用户信息
+#[derive(Debug, FromRow, Serialize)]
+pub struct AdminUserView {
+    pub id: i32,
+    pub username: String,
+    pub email: Option<String>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub display_name: Option<String>,
+    pub role: Role,
+    pub two_factor_enabled: bool,
+    pub disabled_at: Option<DateTime<Utc>>,
+    pub deletion_scheduled_at: Option<DateTime<Utc>>,
+    /// 被管理员停用的时间 (用户申请注销导致的停用为空)
+    pub suspended_at: Option<DateTime<Utc>>,
+    pub last_active_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateRoleSchema {
+    pub role: Role,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct InstanceStats {
+    pub users_total: i64,
+    pub admins: i64,
+    pub users_disabled: i64,
+    pub users_pending_deletion: i64,
+    pub users_with_2fa: i64,
+    pub users_registered_30d: i64,
+    pub users_active_30d: i64,
+    pub active_sessions: i64,
+    pub personal_access_tokens: i64,
+    pub projects: i64,
+    pub issues: i64,
+    pub comments: i64,
+}
@@ -0,0 +1,261 @@
+// src/oidc.rs
+// OpenID Connect 登录：授权码模式 + PKCE
+// 负责发现文档、授权地址、换取令牌与 ID Token 校验；用户的创建与关联在 handlers 中完成
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// 发现文档与 JWKS 的缓存时间
+const METADATA_TTL: Duration = Duration::from_secs(3600);
+
+/// 只接受非对称签名的 ID Token
+const ALLOWED_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    /// 公共客户端 (仅 PKCE) 可以不配置
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    pub scopes: String,
+}
+
+impl OidcConfig {
+    /// 未配置 OIDC_ISSUER 时返回 None (关闭 OIDC 登录)
+    pub fn from_env(app_base_url: &str) -> Result<Option<Self>, String> {
+        let issuer = match std::env::var("OIDC_ISSUER") {
+            Ok(issuer) => issuer.trim_end_matches('/').to_string(),
+            Err(_) => return Ok(None),
+        };
+        let client_id = std::env::var("OIDC_CLIENT_ID").map_err(|_| "OIDC_CLIENT_ID must be set")?;
+
+        Ok(Some(OidcConfig {
+            issuer,
+            client_id,
+            client_secret: std::env::var("OIDC_CLIENT_SECRET").ok(),
+            redirect_uri: std::env::var("OIDC_REDIRECT_URI")
+                .unwrap_or_else(|_| format!("{}/oidc/callback", app_base_url)),
+            scopes: std::env::var("OIDC_SCOPES").unwrap_or_else(|_| "openid profile email".into()),
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+struct Cached<T> {
+    value: T,
+    fetched_at: Instant,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// ID Token 中我们关心的字段
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    pub name: Option<String>,
+}
+
+/// PKCE 参数与 state/nonce，需在回调前保存
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+pub struct OidcClient {
+    config: OidcConfig,
+    http: reqwest::Client,
+    metadata: RwLock<Option<Cached<ProviderMetadata>>>,
+    jwks: RwLock<Option<Cached<JwkSet>>>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Self {
+        OidcClient {
+            config,
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("failed to build HTTP client"),
+            metadata: RwLock::new(None),
+            jwks: RwLock::new(None),
+        }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.config.issuer
+    }
+
+    async fn metadata(&self) -> Result<ProviderMetadata, String> {
+        if let Some(cached) = self.metadata.read().await.as_ref() {
+            if cached.fetched_at.elapsed() < METADATA_TTL {
+                return Ok(cached.value.clone());
+            }
+        }
+
+        let url = format!("{}/.well-known/openid-configuration", self.config.issuer);
+        let metadata: ProviderMetadata = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("获取 OIDC 发现文档失败: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("OIDC 发现文档格式错误: {}", e))?;
+
+        if metadata.issuer.trim_end_matches('/') != self.config.issuer {
+            return Err(format!("OIDC issuer 不匹配: {}", metadata.issuer));
+        }
+
+        *self.metadata.write().await = Some(Cached { value: metadata.clone(), fetched_at: Instant::now() });
+        Ok(metadata)
+    }
+
+    /// 获取身份提供方的公钥；遇到未知 kid 时强制刷新一次 (对方可能刚轮换了密钥)
+    async fn decoding_key(&self, jwks_uri: &str, kid: Option<&str>) -> Result<DecodingKey, String> {
+        for force_refresh in [false, true] {
+            let fresh = !force_refresh
+                && self
+                    .jwks
+                    .read()
+                    .await
+                    .as_ref()
+                    .is_some_and(|c| c.fetched_at.elapsed() < METADATA_TTL);
+
+            if !fresh {
+                let jwks: JwkSet = self
+                    .http
+                    .get(jwks_uri)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| format!("获取 OIDC JWKS 失败: {}", e))?
+                    .json()
+                    .await
+                    .map_err(|e| format!("OIDC JWKS 格式错误: {}", e))?;
+                *self.jwks.write().await = Some(Cached { value: jwks, fetched_at: Instant::now() });
+            }
+
+            let guard = self.jwks.read().await;
+            let jwks = &guard.as_ref().expect("jwks cached").value;
+            let jwk = match kid {
+                Some(kid) => jwks.find(kid),
+                // 没有 kid 时只接受唯一的一把密钥
+                None if jwks.keys.len() == 1 => jwks.keys.first(),
+                None => None,
+            };
+            if let Some(jwk) = jwk {
+                return DecodingKey::from_jwk(jwk).map_err(|e| e.to_string());
+            }
+        }
+        Err("ID Token 的签名密钥不存在".into())
+    }
+
+    /// 生成授权地址 (带 state、nonce 与 S256 PKCE challenge)
+    pub async fn authorization_request(&self) -> Result<AuthorizationRequest, String> {
+        let metadata = self.metadata().await?;
+        let state = crate::auth::generate_secure_token();
+        let nonce = crate::auth::generate_secure_token();
+        let code_verifier = crate::auth::generate_secure_token();
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let url = reqwest::Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("scope", self.config.scopes.as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", code_challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| format!("authorization_endpoint 无效: {}", e))?;
+
+        Ok(AuthorizationRequest { url: url.to_string(), state, nonce, code_verifier })
+    }
+
+    /// 用授权码换取 ID Token 并完成校验 (签名、iss、aud、exp、nonce)
+    pub async fn exchange_code(&self, code: &str, code_verifier: &str, nonce: &str) -> Result<IdTokenClaims, String> {
+        let metadata = self.metadata().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        let response = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| format!("请求 OIDC token 端点失败: {}", e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("OIDC token 端点返回 {}: {}", status, body));
+        }
+        let tokens: TokenResponse = response.json().await.map_err(|e| format!("OIDC token 响应格式错误: {}", e))?;
+
+        let header = decode_header(&tokens.id_token).map_err(|e| e.to_string())?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(format!("不支持的 ID Token 签名算法: {:?}", header.alg));
+        }
+        let key = self.decoding_key(&metadata.jwks_uri, header.kid.as_deref()).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        let claims = decode::<IdTokenClaims>(&tokens.id_token, &key, &validation)
+            .map_err(|e| format!("ID Token 校验失败: {}", e))?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err("ID Token nonce 不匹配".into());
+        }
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests;
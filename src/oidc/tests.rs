@@ -0,0 +1,444 @@
+// src/oidc/tests.rs
+// 用本地模拟的身份提供方 (发现文档 / JWKS / token 端点) 测试 OIDC 登录
+// ID Token 校验不依赖数据库；回调流程 (state、创建与关联用户) 需要 DATABASE_URL，每个测试使用独立的临时数据库
+// 需要数据库的测试默认忽略，用 `cargo test -- --ignored` 运行；未设置 DATABASE_URL 时直接失败
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::pkcs8::EncodePrivateKey;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use rand_core::{OsRng, RngCore};
+use serde_json::{json, Value};
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+use super::{OidcClient, OidcConfig};
+use crate::auth::{hash_token, AuthUser, ClientInfo, Credential, Role};
+use crate::config::{Config, EmailVerificationPolicy, RegistrationPolicy};
+use crate::handlers::{link_oidc_handler, oidc_authorize_handler, oidc_callback_handler};
+use crate::models::{OidcAuthorizeQuery, OidcCallbackSchema};
+use crate::validation::ValidatedJson;
+use crate::{AppError, AppState};
+
+const CLIENT_ID: &str = "planner";
+const KID: &str = "idp-1";
+
+fn ed25519_key() -> ed25519_dalek::SigningKey {
+    let mut seed = [0u8; 32];
+    OsRng.fill_bytes(&mut seed);
+    ed25519_dalek::SigningKey::from_bytes(&seed)
+}
+
+fn encoding_key(key: &ed25519_dalek::SigningKey) -> EncodingKey {
+    EncodingKey::from_ed_der(key.to_pkcs8_der().expect("PKCS#8 编码失败").as_bytes())
+}
+
+// --- 模拟的身份提供方 ---
+
+struct Provider {
+    issuer: String,
+    key: EncodingKey,
+    /// 与 JWKS 中 kid 相同、但不在 JWKS 里的另一把密钥，用于伪造签名
+    forged_key: EncodingKey,
+    public_key: [u8; 32],
+    /// token 端点下一次返回的 ID Token
+    next_id_token: Mutex<Option<String>>,
+}
+
+impl Provider {
+    async fn start() -> Arc<Provider> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let key = ed25519_key();
+        let provider = Arc::new(Provider {
+            issuer: format!("http://{}", addr),
+            key: encoding_key(&key),
+            forged_key: encoding_key(&ed25519_key()),
+            public_key: key.verifying_key().to_bytes(),
+            next_id_token: Mutex::new(None),
+        });
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(provider.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        provider
+    }
+
+    fn client(&self) -> OidcClient {
+        OidcClient::new(OidcConfig {
+            issuer: self.issuer.clone(),
+            client_id: CLIENT_ID.into(),
+            client_secret: None,
+            redirect_uri: "http://localhost:5173/oidc/callback".into(),
+            scopes: "openid profile email".into(),
+        })
+    }
+
+    fn claims(&self, sub: &str, nonce: &str) -> Value {
+        let now = chrono::Utc::now().timestamp();
+        json!({
+            "iss": self.issuer,
+            "aud": CLIENT_ID,
+            "sub": sub,
+            "iat": now,
+            "exp": now + 300,
+            "nonce": nonce,
+            "email": format!("{}@idp.test", sub),
+            "email_verified": true,
+            "preferred_username": sub,
+        })
+    }
+
+    /// 让 token 端点下一次返回用指定密钥签名的 ID Token
+    fn respond_with(&self, claims: &Value, forged: bool) {
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(KID.into());
+        let key = if forged { &self.forged_key } else { &self.key };
+        *self.next_id_token.lock().unwrap() = Some(encode(&header, claims, key).unwrap());
+    }
+}
+
+async fn discovery(State(provider): State<Arc<Provider>>) -> Json<Value> {
+    Json(json!({
+        "issuer": provider.issuer,
+        "authorization_endpoint": format!("{}/authorize", provider.issuer),
+        "token_endpoint": format!("{}/token", provider.issuer),
+        "jwks_uri": format!("{}/jwks", provider.issuer),
+    }))
+}
+
+async fn jwks(State(provider): State<Arc<Provider>>) -> Json<Value> {
+    Json(json!({ "keys": [{
+        "kty": "OKP",
+        "crv": "Ed25519",
+        "alg": "EdDSA",
+        "use": "sig",
+        "kid": KID,
+        "x": URL_SAFE_NO_PAD.encode(provider.public_key),
+    }] }))
+}
+
+async fn token(State(provider): State<Arc<Provider>>) -> Response {
+    match provider.next_id_token.lock().unwrap().take() {
+        Some(id_token) => Json(json!({ "access_token": "unused", "token_type": "Bearer", "id_token": id_token }))
+            .into_response(),
+        None => (StatusCode::BAD_REQUEST, Json(json!({ "error": "invalid_grant" }))).into_response(),
+    }
+}
+
+// --- ID Token 校验 ---
+
+#[tokio::test]
+async fn accepts_valid_id_token() {
+    let provider = Provider::start().await;
+    provider.respond_with(&provider.claims("alice", "n-1"), false);
+
+    let claims = provider.client().exchange_code("code", "verifier", "n-1").await.unwrap();
+    assert_eq!(claims.sub, "alice");
+    assert!(claims.email_verified);
+}
+
+#[tokio::test]
+async fn rejects_nonce_mismatch() {
+    let provider = Provider::start().await;
+    provider.respond_with(&provider.claims("alice", "n-other"), false);
+
+    let err = provider.client().exchange_code("code", "verifier", "n-1").await.unwrap_err();
+    assert!(err.contains("nonce"), "{}", err);
+}
+
+#[tokio::test]
+async fn rejects_bad_signature() {
+    let provider = Provider::start().await;
+    provider.respond_with(&provider.claims("alice", "n-1"), true);
+
+    let err = provider.client().exchange_code("code", "verifier", "n-1").await.unwrap_err();
+    assert!(err.starts_with("ID Token 校验失败"), "{}", err);
+}
+
+#[tokio::test]
+async fn rejects_wrong_audience() {
+    let provider = Provider::start().await;
+    let mut claims = provider.claims("alice", "n-1");
+    claims["aud"] = json!("another-client");
+    provider.respond_with(&claims, false);
+
+    let err = provider.client().exchange_code("code", "verifier", "n-1").await.unwrap_err();
+    assert!(err.starts_with("ID Token 校验失败"), "{}", err);
+}
+
+// --- 回调流程 (需要数据库) ---
+
+struct TestApp {
+    state: AppState,
+    provider: Arc<Provider>,
+    admin: PgPool,
+    database: String,
+    keys_dir: std::path::PathBuf,
+}
+
+impl TestApp {
+    async fn start() -> TestApp {
+        TestApp::start_with(RegistrationPolicy::Open).await
+    }
+
+    async fn start_with(registration: RegistrationPolicy) -> TestApp {
+        dotenvy::dotenv().ok();
+        let url = std::env::var("DATABASE_URL").expect("需要数据库的 OIDC 测试必须设置 DATABASE_URL");
+
+        let admin = PgPool::connect(&url).await.expect("连接数据库失败");
+        let database = format!("mlp_test_{}", Uuid::new_v4().simple());
+        admin.execute(format!("CREATE DATABASE {}", database).as_str()).await.unwrap();
+        let mut test_url = reqwest::Url::parse(&url).unwrap();
+        test_url.set_path(&database);
+        let db = PgPool::connect(test_url.as_str()).await.unwrap();
+        db.execute(include_str!("../../init_schema.sql")).await.unwrap();
+
+        let keys_dir = std::env::temp_dir().join(&database);
+        std::fs::create_dir_all(&keys_dir).unwrap();
+        let pem = ed25519_key().to_pkcs8_pem(Default::default()).unwrap();
+        std::fs::write(keys_dir.join("ed.pem"), pem.as_bytes()).unwrap();
+        std::fs::write(
+            keys_dir.join("keys.json"),
+            r#"{ "keys": [{ "kid": "ed", "alg": "EdDSA", "file": "ed.pem", "status": "active" }] }"#,
+        )
+        .unwrap();
+
+        let provider = Provider::start().await;
+        let state = AppState {
+            db,
+            config: Arc::new(Config {
+                app_base_url: "http://localhost:5173".into(),
+                argon2: argon2::Params::default(),
+                email_verification: EmailVerificationPolicy::Optional,
+                account_deletion_grace_days: 30,
+                registration,
+                trusted_proxies: Vec::new(),
+            }),
+            mailer: Arc::new(crate::mailer::FileMailer::new(None)),
+            keys: Arc::new(crate::keys::KeyManager::load(&keys_dir).unwrap()),
+            oidc: Some(Arc::new(provider.client())),
+            revocation: Arc::new(crate::revocation::TokenRevocation::default()),
+            throttle: Arc::new(crate::throttle::LoginThrottle::default()),
+        };
+        TestApp { state, provider, admin, database, keys_dir }
+    }
+
+    async fn finish(self) {
+        self.state.db.close().await;
+        self.admin
+            .execute(format!("DROP DATABASE {} WITH (FORCE)", self.database).as_str())
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(&self.keys_dir).ok();
+    }
+
+    /// 完成一次授权跳转：返回回调需要提交的 state，并让身份提供方为 sub 签发带正确 nonce 的 ID Token
+    fn authorized(&self, authorization_url: &str, sub: &str) -> String {
+        let nonce = query_param(authorization_url, "nonce");
+        self.provider.respond_with(&self.provider.claims(sub, &nonce), false);
+        query_param(authorization_url, "state")
+    }
+
+    async fn authorize(&self, invite_code: Option<&str>) -> Result<String, AppError> {
+        let query = OidcAuthorizeQuery { invite_code: invite_code.map(Into::into) };
+        let response = oidc_authorize_handler(State(self.state.clone()), Query(query)).await?;
+        Ok(response.0.authorization_url)
+    }
+
+    async fn begin_login(&self, sub: &str) -> String {
+        let url = self.authorize(None).await.unwrap();
+        self.authorized(&url, sub)
+    }
+
+    async fn begin_link(&self, user_id: i32, sub: &str) -> String {
+        let user = AuthUser {
+            id: user_id,
+            username: String::new(),
+            role: Role::User,
+            credential: Credential::Jwt { session_id: None, jti: Uuid::new_v4(), exp: usize::MAX },
+        };
+        let response = link_oidc_handler(user, State(self.state.clone())).await.unwrap();
+        self.authorized(&response.0.authorization_url, sub)
+    }
+
+    async fn callback(&self, state: &str) -> Result<(), AppError> {
+        let client = ClientInfo { user_agent: None, ip_address: None };
+        let body = OidcCallbackSchema { code: "code".into(), state: state.into(), invite_code: None };
+        oidc_callback_handler(State(self.state.clone()), client, ValidatedJson(body)).await.map(|_| ())
+    }
+
+    async fn identity_owner(&self, sub: &str) -> Option<i32> {
+        sqlx::query_scalar("SELECT user_id FROM user_identities WHERE subject = $1")
+            .bind(sub)
+            .fetch_optional(&self.state.db)
+            .await
+            .unwrap()
+    }
+
+    async fn user_count(&self) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM users").fetch_one(&self.state.db).await.unwrap()
+    }
+
+    async fn create_local_user(&self, username: &str) -> i32 {
+        sqlx::query_scalar("INSERT INTO users (username, password_hash) VALUES ($1, '') RETURNING id")
+            .bind(username)
+            .fetch_one(&self.state.db)
+            .await
+            .unwrap()
+    }
+}
+
+fn query_param(url: &str, name: &str) -> String {
+    reqwest::Url::parse(url)
+        .unwrap()
+        .query_pairs()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "需要 DATABASE_URL"]
+async fn callback_rejects_unknown_and_replayed_state() {
+    let app = TestApp::start().await;
+
+    let state = app.begin_login("alice").await;
+    assert!(matches!(app.callback("not-the-state").await, Err(AppError::BadRequest(_))));
+
+    app.callback(&state).await.unwrap();
+    // state 单次有效，重放会被拒绝
+    assert!(matches!(app.callback(&state).await, Err(AppError::BadRequest(_))));
+
+    app.finish().await;
+}
+
+#[tokio::test]
+#[ignore = "需要 DATABASE_URL"]
+async fn callback_rejects_nonce_mismatch_and_bad_signature() {
+    let app = TestApp::start().await;
+
+    // ID Token 中的 nonce 与授权请求保存的不一致
+    let state = app.begin_login("alice").await;
+    app.provider.respond_with(&app.provider.claims("alice", "n-other"), false);
+    assert!(matches!(app.callback(&state).await, Err(AppError::Auth(_))));
+
+    // nonce 正确，但签名不是身份提供方的密钥
+    let url = app.authorize(None).await.unwrap();
+    let claims = app.provider.claims("alice", &query_param(&url, "nonce"));
+    app.provider.respond_with(&claims, true);
+    let state = query_param(&url, "state");
+    assert!(matches!(app.callback(&state).await, Err(AppError::Auth(_))));
+
+    assert_eq!(app.user_count().await, 0);
+    assert_eq!(app.identity_owner("alice").await, None);
+
+    app.finish().await;
+}
+
+#[tokio::test]
+#[ignore = "需要 DATABASE_URL"]
+async fn first_login_creates_user_and_later_logins_reuse_it() {
+    let app = TestApp::start().await;
+
+    let state = app.begin_login("alice").await;
+    app.callback(&state).await.unwrap();
+    let owner = app.identity_owner("alice").await.expect("应创建第三方身份");
+    let (username, verified): (String, bool) =
+        sqlx::query_as("SELECT username, email_verified_at IS NOT NULL FROM users WHERE id = $1")
+            .bind(owner)
+            .fetch_one(&app.state.db)
+            .await
+            .unwrap();
+    assert_eq!(username, "alice");
+    assert!(verified, "身份提供方确认过的邮箱视为已验证");
+
+    let state = app.begin_login("alice").await;
+    app.callback(&state).await.unwrap();
+    assert_eq!(app.identity_owner("alice").await, Some(owner));
+    assert_eq!(app.user_count().await, 1);
+
+    app.finish().await;
+}
+
+#[tokio::test]
+#[ignore = "需要 DATABASE_URL"]
+async fn link_binds_identity_to_signed_in_user() {
+    let app = TestApp::start().await;
+    let bob = app.create_local_user("bob").await;
+    let carol = app.create_local_user("carol").await;
+
+    let state = app.begin_link(bob, "bob-at-idp").await;
+    app.callback(&state).await.unwrap();
+    assert_eq!(app.identity_owner("bob-at-idp").await, Some(bob));
+    assert_eq!(app.user_count().await, 2, "关联不应创建新用户");
+
+    // 之后用该身份登录进入 bob 的账户
+    let state = app.begin_login("bob-at-idp").await;
+    app.callback(&state).await.unwrap();
+    assert_eq!(app.user_count().await, 2);
+
+    // 已关联到 bob 的身份不能再关联给 carol
+    let state = app.begin_link(carol, "bob-at-idp").await;
+    assert!(matches!(app.callback(&state).await, Err(AppError::BadRequest(_))));
+    assert_eq!(app.identity_owner("bob-at-idp").await, Some(bob));
+
+    app.finish().await;
+}
+
+#[tokio::test]
+#[ignore = "需要 DATABASE_URL"]
+async fn invite_is_checked_before_state_and_code_are_consumed() {
+    let app = TestApp::start_with(RegistrationPolicy::InviteOnly).await;
+    let inviter = app.create_local_user("inviter").await;
+    sqlx::query(
+        r#"INSERT INTO invite_codes (created_by, code_hash, code_prefix, expires_at)
+           VALUES ($1, $2, 'good', NOW() + INTERVAL '1 day')"#,
+    )
+    .bind(inviter)
+    .bind(hash_token("good"))
+    .execute(&app.state.db)
+    .await
+    .unwrap();
+
+    // 无效的邀请码在跳转前就被拒绝
+    assert!(matches!(app.authorize(Some("bad")).await, Err(AppError::Forbidden(_))));
+
+    let url = app.authorize(Some(" good ")).await.unwrap();
+    let state = app.authorized(&url, "dave");
+
+    // 邀请码在回调前被用完：回调失败，但 state 仍然保留
+    sqlx::query("UPDATE invite_codes SET use_count = max_uses").execute(&app.state.db).await.unwrap();
+    assert!(matches!(app.callback(&state).await, Err(AppError::Forbidden(_))));
+    let pending: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM oidc_login_states")
+        .fetch_one(&app.state.db)
+        .await
+        .unwrap();
+    assert_eq!(pending, 1);
+
+    // 邀请码恢复可用后，用同一个 state 完成注册，并核销授权时保存的邀请码
+    sqlx::query("UPDATE invite_codes SET use_count = 0").execute(&app.state.db).await.unwrap();
+    app.callback(&state).await.unwrap();
+    let owner = app.identity_owner("dave").await.expect("应创建第三方身份");
+    let invited_by: Option<i32> = sqlx::query_scalar("SELECT invited_by FROM users WHERE id = $1")
+        .bind(owner)
+        .fetch_one(&app.state.db)
+        .await
+        .unwrap();
+    assert_eq!(invited_by, Some(inviter));
+    let used: i32 = sqlx::query_scalar("SELECT use_count FROM invite_codes").fetch_one(&app.state.db).await.unwrap();
+    assert_eq!(used, 1);
+
+    app.finish().await;
+}
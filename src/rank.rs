@@ -0,0 +1,192 @@
+// src/rank.rs
+// 任务手动排序的分数索引 (fractional indexing)：排序键是 base62 字符串，按字节序比较
+// 在任意两个键之间总能生成新键，拖拽排序只需更新被移动的任务；键不会以 '0' 结尾
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const BASE: usize = DIGITS.len();
+
+/// 排序键超过该长度的项目会被后台任务重新均匀分配
+pub const REBALANCE_RANK_LENGTH: i32 = 24;
+
+fn digit(c: u8) -> usize {
+    DIGITS.iter().position(|&d| d == c).unwrap_or(0)
+}
+
+/// 要求 lower < upper (upper 为 None 表示无上界)，且两者都不以 '0' 结尾
+fn midpoint(lower: &[u8], upper: Option<&[u8]>) -> Vec<u8> {
+    if let Some(upper) = upper {
+        // 跳过公共前缀 (lower 不足的位按 '0' 补齐)
+        let n = upper
+            .iter()
+            .enumerate()
+            .take_while(|&(i, &c)| lower.get(i).copied().unwrap_or(b'0') == c)
+            .count();
+        if n > 0 {
+            let mut key = upper[..n].to_vec();
+            key.extend(midpoint(lower.get(n..).unwrap_or_default(), Some(&upper[n..])));
+            return key;
+        }
+    }
+
+    let low = lower.first().map_or(0, |&c| digit(c));
+    let high = upper.map_or(BASE, |u| digit(u[0]));
+    if high - low > 1 {
+        vec![DIGITS[(low + high).div_ceil(2)]]
+    } else if let Some(upper) = upper.filter(|u| u.len() > 1) {
+        vec![upper[0]]
+    } else {
+        let mut key = vec![DIGITS[low]];
+        key.extend(midpoint(lower.get(1..).unwrap_or_default(), None));
+        key
+    }
+}
+
+/// 比 lower 大的短键：第一个不是 'z' 的位加一并截断；全是 'z' 时在末尾追加 '1'
+/// 连续追加时键长约每 61 次才增加一位
+fn after(lower: &[u8]) -> Vec<u8> {
+    match lower.iter().position(|&c| c != DIGITS[BASE - 1]) {
+        Some(i) => {
+            let mut key = lower[..i].to_vec();
+            key.push(DIGITS[digit(lower[i]) + 1]);
+            key
+        }
+        None => [lower, b"1"].concat(),
+    }
+}
+
+/// 比 upper 小的短键：第一个非 '0' 位减一并截断；该位是 '1' 时改为 "0z"
+fn before(upper: &[u8]) -> Vec<u8> {
+    let i = upper.iter().position(|&c| c != b'0').unwrap_or(upper.len());
+    let mut key = upper[..i].to_vec();
+    match upper.get(i).map(|&c| digit(c)) {
+        Some(d) if d > 1 => key.push(DIGITS[d - 1]),
+        _ => key.extend([b'0', DIGITS[BASE - 1]]),
+    }
+    key
+}
+
+/// 生成介于 lower 与 upper 之间的排序键 (None 表示该侧无边界)；lower >= upper 时返回 None
+pub fn between(lower: Option<&str>, upper: Option<&str>) -> Option<String> {
+    let key = match (lower, upper) {
+        (Some(l), Some(u)) if l >= u => return None,
+        (Some(l), Some(u)) => midpoint(l.as_bytes(), Some(u.as_bytes())),
+        // 追加到末尾 / 插入到开头是最常见的操作，只移动一位以减缓键长增长
+        (Some(l), None) => after(l.as_bytes()),
+        (None, Some(u)) => before(u.as_bytes()),
+        (None, None) => midpoint(b"", None),
+    };
+    Some(String::from_utf8(key).expect("排序键只包含 ASCII 字符"))
+}
+
+/// 为 count 个任务生成等长、均匀分布的排序键 (重新平衡时使用)
+pub fn spread(count: usize) -> Vec<String> {
+    // 相邻两个键之间至少留出 BASE 个空位
+    let slots = (count as u128 + 1) * BASE as u128;
+    let mut length = 1;
+    let mut capacity = BASE as u128;
+    while capacity < slots {
+        length += 1;
+        capacity *= BASE as u128;
+    }
+    let step = capacity / (count as u128 + 1);
+
+    (1..=count as u128)
+        .map(|i| {
+            let mut value = i * step;
+            let mut key = vec![b'0'; length];
+            for c in key.iter_mut().rev() {
+                *c = DIGITS[(value % BASE as u128) as usize];
+                value /= BASE as u128;
+            }
+            // 去掉末尾的 '0' 不影响顺序，且保证之后还能在前面插入
+            while key.last() == Some(&b'0') {
+                key.pop();
+            }
+            String::from_utf8(key).expect("排序键只包含 ASCII 字符")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_between(lower: Option<&str>, upper: Option<&str>) -> String {
+        let key = between(lower, upper).unwrap();
+        assert!(lower.is_none_or(|l| l < key.as_str()), "{:?} < {}", lower, key);
+        assert!(upper.is_none_or(|u| key.as_str() < u), "{} < {:?}", key, upper);
+        assert!(!key.is_empty() && !key.ends_with('0'), "{}", key);
+        key
+    }
+
+    #[test]
+    fn first_key() {
+        assert_eq!(between(None, None).as_deref(), Some("V"));
+    }
+
+    #[test]
+    fn repeated_appends_and_prepends() {
+        let mut last = assert_between(None, None);
+        for _ in 0..1000 {
+            last = assert_between(Some(&last), None);
+        }
+        assert!(last.len() <= 20, "{}", last);
+
+        let mut first = assert_between(None, None);
+        for _ in 0..1000 {
+            first = assert_between(None, Some(&first));
+        }
+        assert!(first.len() <= 20, "{}", first);
+    }
+
+    #[test]
+    fn adjacent_digits() {
+        assert_between(Some("a1"), Some("a2"));
+        assert_between(Some("a"), Some("b"));
+        assert_between(Some("y"), Some("z"));
+        assert_between(Some("z"), None);
+        assert_between(None, Some("1"));
+    }
+
+    #[test]
+    fn upper_extends_lower() {
+        assert_between(Some("a"), Some("a01"));
+        assert_between(Some("a"), Some("a1"));
+        assert_between(Some("a0V"), Some("a1"));
+    }
+
+    #[test]
+    fn repeated_inserts_between_neighbours() {
+        let (mut low, mut high) = ("a".to_string(), "b".to_string());
+        for i in 0..200 {
+            let key = assert_between(Some(&low), Some(&high));
+            if i % 2 == 0 {
+                low = key;
+            } else {
+                high = key;
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unordered_bounds() {
+        assert_eq!(between(Some("b"), Some("a")), None);
+        assert_eq!(between(Some("a"), Some("a")), None);
+    }
+
+    #[test]
+    fn spread_is_strictly_increasing() {
+        assert!(spread(0).is_empty());
+        for count in [1, 2, 61, 62, 63, 1000, 5000] {
+            let keys = spread(count);
+            assert_eq!(keys.len(), count);
+            assert!(keys.iter().all(|k| !k.is_empty() && !k.ends_with('0')), "{:?}", keys);
+            assert!(keys.windows(2).all(|w| w[0] < w[1]), "count = {}", count);
+            // 重新平衡后的键仍然可以在两端和相邻键之间插入
+            assert_between(None, Some(&keys[0]));
+            assert_between(Some(&keys[count - 1]), None);
+            for w in keys.windows(2) {
+                assert_between(Some(&w[0]), Some(&w[1]));
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+// src/revocation.rs
+// Access Token 吊销检查：用户级 token_version + 按 jti 的黑名单
+// 数据以 Postgres 为准，进程内缓存避免每个请求都查库
+use crate::AppError;
+use chrono::{DateTime, TimeZone, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// 缓存有效期：其他实例上的吊销操作最多延迟这么久生效
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct Denylist {
+    /// jti -> Token 原本的过期时间 (Unix 秒)
+    entries: HashMap<Uuid, i64>,
+    synced_at: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct TokenRevocation {
+    /// user_id -> (token_version, 读取时间)
+    versions: RwLock<HashMap<i32, (i32, Instant)>>,
+    denylist: RwLock<Denylist>,
+}
+
+impl TokenRevocation {
+    /// 检查 Token 是否已被吊销 (版本过期或 jti 在黑名单中)
+    pub async fn check(&self, db: &PgPool, user_id: i32, token_version: i32, jti: Uuid) -> Result<(), AppError> {
+        if self.current_version(db, user_id).await? != Some(token_version) {
+            return Err(AppError::Auth("Token 已失效，请重新登录".into()));
+        }
+        if self.is_denied(db, jti).await? {
+            return Err(AppError::Auth("Token 已失效，请重新登录".into()));
+        }
+        Ok(())
+    }
+
+    /// 将单个 Access Token 加入黑名单 (例如退出登录时)
+    pub async fn deny(&self, db: &PgPool, user_id: i32, jti: Uuid, exp: usize) -> Result<(), AppError> {
+        let expires_at = Utc
+            .timestamp_opt(exp as i64, 0)
+            .single()
+            .unwrap_or_else(Utc::now);
+
+        sqlx::query(
+            r#"INSERT INTO revoked_access_tokens (jti, user_id, expires_at) VALUES ($1, $2, $3)
+               ON CONFLICT (jti) DO NOTHING"#,
+        )
+        .bind(jti)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(db)
+        .await?;
+
+        self.denylist.write().unwrap().entries.insert(jti, exp as i64);
+        Ok(())
+    }
+
+    /// 递增用户的 token_version，使其此前签发的所有 Access Token 失效
+    /// 通常在事务中执行：提交前其他请求仍会读到旧版本，调用方必须在提交后调用 invalidate
+    pub async fn bump_version<'e, E>(&self, executor: E, user_id: i32) -> Result<i32, AppError>
+    where
+        E: sqlx::PgExecutor<'e>,
+    {
+        let (version,): (i32,) = sqlx::query_as(
+            "UPDATE users SET token_version = token_version + 1 WHERE id = $1 RETURNING token_version",
+        )
+        .bind(user_id)
+        .fetch_one(executor)
+        .await?;
+        Ok(version)
+    }
+
+    /// 丢弃缓存的 token_version，下次检查时从数据库读取
+    /// 只能在修改版本的事务提交之后调用，否则并发请求可能把旧版本重新写入缓存
+    pub fn invalidate(&self, user_id: i32) {
+        self.versions.write().unwrap().remove(&user_id);
+    }
+
+    async fn current_version(&self, db: &PgPool, user_id: i32) -> Result<Option<i32>, AppError> {
+        if let Some((version, fetched_at)) = self.versions.read().unwrap().get(&user_id) {
+            if fetched_at.elapsed() < CACHE_TTL {
+                return Ok(Some(*version));
+            }
+        }
+
+        let row: Option<(i32,)> = sqlx::query_as("SELECT token_version FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(db)
+            .await?;
+
+        let mut versions = self.versions.write().unwrap();
+        match row {
+            Some((version,)) => {
+                versions.insert(user_id, (version, Instant::now()));
+                Ok(Some(version))
+            }
+            // 用户已被删除
+            None => {
+                versions.remove(&user_id);
+                Ok(None)
+            }
+        }
+    }
+
+    async fn is_denied(&self, db: &PgPool, jti: Uuid) -> Result<bool, AppError> {
+        let stale = {
+            let denylist = self.denylist.read().unwrap();
+            if denylist.entries.contains_key(&jti) {
+                return Ok(true);
+            }
+            denylist.synced_at.is_none_or(|t| t.elapsed() >= CACHE_TTL)
+        };
+
+        if stale {
+            self.sync_denylist(db).await?;
+            return Ok(self.denylist.read().unwrap().entries.contains_key(&jti));
+        }
+        Ok(false)
+    }
+
+    /// 从数据库重新加载黑名单，并顺带清理已过期的记录
+    async fn sync_denylist(&self, db: &PgPool) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM revoked_access_tokens WHERE expires_at < NOW()")
+            .execute(db)
+            .await?;
+
+        let rows: Vec<(Uuid, DateTime<Utc>)> =
+            sqlx::query_as("SELECT jti, expires_at FROM revoked_access_tokens")
+                .fetch_all(db)
+                .await?;
+
+        let mut denylist = self.denylist.write().unwrap();
+        denylist.entries = rows.into_iter().map(|(jti, exp)| (jti, exp.timestamp())).collect();
+        denylist.synced_at = Some(Instant::now());
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+// src/throttle.rs
+// 登录防爆破：按用户名 / IP / 两步验证用户分别计数失败次数
+// 超过免费次数后按指数退避锁定，锁定期间直接返回 429 (不再做密码校验)
+use crate::AppError;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 最后一次失败超过这么久后计数清零
+const RESET_AFTER: Duration = Duration::from_secs(60 * 60);
+/// 第一次锁定的时长，之后每次失败翻倍
+const BASE_LOCKOUT: Duration = Duration::from_secs(2);
+/// 单次锁定的最长时长
+const MAX_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+/// 记录数超过该值时清理过期记录
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ThrottleKey {
+    /// 针对单个账户的猜密码
+    Username(String),
+    /// 针对单个来源的撞库 (尝试大量不同用户名)
+    Ip(String),
+    /// 两步验证码只有 6 位，按用户限制
+    Mfa(i32),
+}
+
+impl ThrottleKey {
+    pub fn username(username: &str) -> Self {
+        ThrottleKey::Username(username.trim().to_lowercase())
+    }
+
+    /// 锁定前允许的失败次数
+    fn free_attempts(&self) -> u32 {
+        match self {
+            ThrottleKey::Username(_) => 5,
+            ThrottleKey::Ip(_) => 20,
+            ThrottleKey::Mfa(_) => 5,
+        }
+    }
+}
+
+struct Attempts {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct LoginThrottle {
+    entries: Mutex<HashMap<ThrottleKey, Attempts>>,
+}
+
+impl LoginThrottle {
+    /// 任一维度处于锁定期时拒绝，Retry-After 取最长的剩余时间
+    pub fn check(&self, keys: &[ThrottleKey]) -> Result<(), AppError> {
+        self.check_at(keys, Instant::now())
+    }
+
+    pub fn record_failure(&self, keys: &[ThrottleKey]) {
+        self.record_failure_at(keys, Instant::now())
+    }
+
+    fn check_at(&self, keys: &[ThrottleKey], now: Instant) -> Result<(), AppError> {
+        let entries = self.entries.lock().unwrap();
+
+        let wait = keys
+            .iter()
+            .filter_map(|key| entries.get(key)?.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+            .max();
+
+        match wait {
+            Some(wait) => Err(AppError::TooManyRequests((wait.as_millis() as u64).div_ceil(1000))),
+            None => Ok(()),
+        }
+    }
+
+    fn record_failure_at(&self, keys: &[ThrottleKey], now: Instant) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() > PRUNE_THRESHOLD {
+            entries.retain(|_, a| now.duration_since(a.last_failure) < RESET_AFTER);
+        }
+
+        for key in keys {
+            let attempts = entries.entry(key.clone()).or_insert(Attempts {
+                failures: 0,
+                last_failure: now,
+                locked_until: None,
+            });
+            if now.duration_since(attempts.last_failure) >= RESET_AFTER {
+                attempts.failures = 0;
+            }
+            attempts.failures += 1;
+            attempts.last_failure = now;
+
+            if attempts.failures > key.free_attempts() {
+                let exponent = (attempts.failures - key.free_attempts() - 1).min(16);
+                let lockout = (BASE_LOCKOUT * 2u32.pow(exponent)).min(MAX_LOCKOUT);
+                attempts.locked_until = Some(now + lockout);
+                tracing::warn!("登录失败次数过多: {:?}，锁定 {} 秒", key, lockout.as_secs());
+            }
+        }
+    }
+
+    /// 成功后清除计数 (IP 维度不清除，避免攻击者用自己的账户重置计数)
+    pub fn record_success(&self, key: &ThrottleKey) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retry_after(throttle: &LoginThrottle, keys: &[ThrottleKey], now: Instant) -> Option<u64> {
+        match throttle.check_at(keys, now) {
+            Ok(()) => None,
+            Err(AppError::TooManyRequests(secs)) => Some(secs),
+            Err(e) => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn lockout_doubles_after_free_attempts() {
+        let throttle = LoginThrottle::default();
+        let keys = [ThrottleKey::username(" Alice ")];
+        let start = Instant::now();
+
+        for _ in 0..5 {
+            throttle.record_failure_at(&keys, start);
+        }
+        assert_eq!(retry_after(&throttle, &keys, start), None);
+
+        // 第 6 次起锁定 2 秒，之后每次失败翻倍
+        for (i, expected) in [2, 4, 8, 16].into_iter().enumerate() {
+            let now = start + Duration::from_secs(i as u64 * 60);
+            throttle.record_failure_at(&keys, now);
+            assert_eq!(retry_after(&throttle, &keys, now), Some(expected));
+            assert_eq!(retry_after(&throttle, &keys, now + Duration::from_secs(expected)), None);
+        }
+
+        // 用户名不区分大小写与首尾空白
+        assert!(retry_after(&throttle, &[ThrottleKey::username("alice")], start + Duration::from_secs(180)).is_some());
+    }
+
+    #[test]
+    fn lockout_is_capped() {
+        let throttle = LoginThrottle::default();
+        let keys = [ThrottleKey::Mfa(1)];
+        let now = Instant::now();
+        for _ in 0..40 {
+            throttle.record_failure_at(&keys, now);
+        }
+        assert_eq!(retry_after(&throttle, &keys, now), Some(MAX_LOCKOUT.as_secs()));
+    }
+
+    #[test]
+    fn failures_reset_after_quiet_period() {
+        let throttle = LoginThrottle::default();
+        let keys = [ThrottleKey::username("bob")];
+        let start = Instant::now();
+        for _ in 0..6 {
+            throttle.record_failure_at(&keys, start);
+        }
+        assert!(retry_after(&throttle, &keys, start).is_some());
+
+        // 距上次失败不足 RESET_AFTER 时继续累计
+        let later = start + RESET_AFTER - Duration::from_secs(1);
+        throttle.record_failure_at(&keys, later);
+        assert_eq!(retry_after(&throttle, &keys, later), Some(4));
+
+        // 超过 RESET_AFTER 后重新计数，前 5 次失败不再锁定
+        let after_reset = later + RESET_AFTER;
+        for _ in 0..5 {
+            throttle.record_failure_at(&keys, after_reset);
+        }
+        assert_eq!(retry_after(&throttle, &keys, after_reset), None);
+        throttle.record_failure_at(&keys, after_reset);
+        assert_eq!(retry_after(&throttle, &keys, after_reset), Some(2));
+    }
+
+    #[test]
+    fn keys_are_counted_separately() {
+        let throttle = LoginThrottle::default();
+        let keys = [ThrottleKey::username("carol"), ThrottleKey::Ip("192.0.2.1".into())];
+        let now = Instant::now();
+        for _ in 0..6 {
+            throttle.record_failure_at(&keys, now);
+        }
+        // IP 维度允许更多次失败；任一维度锁定即拒绝
+        assert_eq!(retry_after(&throttle, &keys[1..], now), None);
+        assert_eq!(retry_after(&throttle, &keys, now), Some(2));
+
+        // 登录成功只清除对应维度
+        throttle.record_success(&keys[0]);
+        assert_eq!(retry_after(&throttle, &keys, now), None);
+    }
+}
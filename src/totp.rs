@@ -0,0 +1,82 @@
+// src/totp.rs
+// 两步验证：RFC 6238 TOTP (SHA1 / 6 位 / 30 秒) 与一次性恢复码
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use totp_rs::{Algorithm, TOTP};
+
+const ISSUER: &str = "MyLifePlanner";
+const DIGITS: usize = 6;
+const STEP: u64 = 30;
+/// 允许前后各 1 个时间步的时钟偏差
+const SKEW: u64 = 1;
+
+/// 恢复码字符集 (去掉了易混淆的 0/o/1/l)
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghijkmnpqrstuvwxyz23456789";
+
+fn build(secret_base32: &str, account_name: &str) -> Result<TOTP, String> {
+    let secret = totp_rs::Secret::Encoded(secret_base32.to_string())
+        .to_bytes()
+        .map_err(|e| e.to_string())?;
+    // otpauth URI 中 ':' 是分隔符，不能出现在账户名里
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        SKEW as u8,
+        STEP,
+        secret,
+        Some(ISSUER.to_string()),
+        account_name.replace(':', "_"),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// 生成 160 位随机密钥 (Base32)
+pub fn generate_secret() -> String {
+    let mut bytes = vec![0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    totp_rs::Secret::Raw(bytes).to_encoded().to_string()
+}
+
+/// 供验证器 App 扫码的 otpauth:// URI
+pub fn otpauth_uri(secret_base32: &str, username: &str) -> Result<String, String> {
+    Ok(build(secret_base32, username)?.get_url())
+}
+
+/// 校验验证码，成功时返回匹配的时间步
+/// 不大于 last_step 的时间步会被拒绝，防止同一个验证码被重放
+pub fn verify(secret_base32: &str, code: &str, last_step: Option<i64>) -> Option<i64> {
+    let totp = build(secret_base32, "").ok()?;
+    let now = chrono::Utc::now().timestamp() as u64;
+    let current = now / STEP;
+
+    (current.saturating_sub(SKEW)..=current + SKEW)
+        .filter(|step| last_step.is_none_or(|last| *step as i64 > last))
+        .find(|step| constant_time_eq(totp.generate(step * STEP).as_bytes(), code.as_bytes()))
+        .map(|step| step as i64)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// 生成一组恢复码，格式 xxxxx-xxxxx
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|_| {
+            let mut bytes = [0u8; 10];
+            OsRng.fill_bytes(&mut bytes);
+            let chars: String = bytes
+                .iter()
+                .map(|b| RECOVERY_ALPHABET[*b as usize % RECOVERY_ALPHABET.len()] as char)
+                .collect();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect()
+}
+
+/// 用户输入的恢复码统一为小写并去掉分隔符后再哈希
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
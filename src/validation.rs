@@ -4,7 +4,7 @@ use axum::{
     extract::{FromRequest, Request}, // 修正为 Request
     Json,
 };
-use validator::Validate;
+use validator::{Validate, ValidationError};
 use crate::AppError;
 
 pub struct ValidatedJson<T>(pub T);
@@ -29,4 +29,83 @@ where
         
         Ok(ValidatedJson(value))
     }
-}
\ No newline at end of file
+}
+
+/// 时区必须是 IANA 时区名，例如 Asia/Shanghai
+pub fn validate_timezone(timezone: &str) -> Result<(), ValidationError> {
+    timezone
+        .parse::<chrono_tz::Tz>()
+        .map(|_| ())
+        .map_err(|_| ValidationError::new("timezone").with_message("时区无效，请使用 IANA 时区名 (如 Asia/Shanghai)".into()))
+}
+
+/// 语言标签只做格式检查 (如 zh-CN、en)，不校验是否真实存在
+pub fn validate_locale(locale: &str) -> Result<(), ValidationError> {
+    let valid = (2..=16).contains(&locale.len())
+        && locale.split('-').all(|part| {
+            (1..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationError::new("locale").with_message("语言格式无效 (如 zh-CN、en)".into()))
+    }
+}
+
+/// 只接受 http(s) 地址，避免 javascript: 等协议被前端直接渲染
+pub fn validate_http_url(url: &str) -> Result<(), ValidationError> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+        _ => Err(ValidationError::new("url").with_message("地址格式不正确，仅支持 http(s)".into())),
+    }
+}
+
+/// 用户名只能包含字母 (含中文等文字)、数字以及 _ - .，不能有空白、@ 或控制字符
+pub fn validate_username(username: &str) -> Result<(), ValidationError> {
+    if username.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("username").with_message("用户名只能包含字母、数字以及 _ - .".into()))
+    }
+}
+
+/// 名称列表 (工作区默认状态、默认标签等)：每项 1-30 个字符且不能重复
+pub fn validate_name_list(names: &[String]) -> Result<(), ValidationError> {
+    if names.iter().any(|n| n.trim().is_empty() || n.chars().count() > 30) {
+        return Err(ValidationError::new("name_list").with_message("每项需为 1-30 个字符".into()));
+    }
+    let mut seen = std::collections::HashSet::new();
+    if !names.iter().all(|n| seen.insert(n.trim().to_lowercase())) {
+        return Err(ValidationError::new("name_list").with_message("列表中存在重复项".into()));
+    }
+    Ok(())
+}
+
+/// 颜色必须是 #RRGGBB 格式
+pub fn validate_hex_color(color: &str) -> Result<(), ValidationError> {
+    let valid = color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationError::new("color").with_message("颜色格式应为 #RRGGBB".into()))
+    }
+}
+
+/// 标签名不能只有空白 (保存时会去掉首尾空白)，也不能包含逗号 (逗号用于 ?label= 中分隔多个标签)
+pub fn validate_label_name(name: &str) -> Result<(), ValidationError> {
+    if name.trim().is_empty() {
+        Err(ValidationError::new("label_name").with_message("标签名称不能为空".into()))
+    } else if name.contains(',') {
+        Err(ValidationError::new("label_name").with_message("标签名不能包含逗号".into()))
+    } else {
+        Ok(())
+    }
+}
+
+/// 工作区默认标签：在名称列表规则之外，每项还要满足标签名规则
+pub fn validate_label_list(names: &[String]) -> Result<(), ValidationError> {
+    validate_name_list(names)?;
+    names.iter().try_for_each(|n| validate_label_name(n))
+}
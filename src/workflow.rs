@@ -0,0 +1,161 @@
+// src/workflow.rs
+// 项目工作流：新项目的默认状态，以及整体替换工作流时的结构检查
+use std::collections::HashSet;
+
+use crate::models::{StatusCategory, UpdateWorkflowSchema};
+use crate::AppError;
+
+/// 按名称推断状态分类；第一个状态是初始状态，不会被归为已完成/已取消
+fn guess_category(name: &str, is_first: bool) -> StatusCategory {
+    match name.to_lowercase().as_str() {
+        "backlog" | "积压" => StatusCategory::Backlog,
+        _ if is_first => StatusCategory::Unstarted,
+        "todo" | "to do" | "待办" | "未开始" => StatusCategory::Unstarted,
+        "done" | "completed" | "complete" | "完成" | "已完成" => StatusCategory::Completed,
+        "canceled" | "cancelled" | "取消" | "已取消" => StatusCategory::Canceled,
+        _ => StatusCategory::Started,
+    }
+}
+
+/// 由工作区默认状态列表生成新项目的工作流状态 (名称, 分类)
+/// 列表中没有可识别的完成状态时，把最后一个状态当作已完成
+/// 第一个状态不能是已完成，所以工作区设置要求至少两个默认状态
+pub fn default_states(names: &[String]) -> Vec<(String, StatusCategory)> {
+    let mut states: Vec<(String, StatusCategory)> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.clone(), guess_category(name, i == 0)))
+        .collect();
+    if states.len() > 1 && !states.iter().any(|(_, c)| *c == StatusCategory::Completed) {
+        if let Some(last) = states.last_mut() {
+            last.1 = StatusCategory::Completed;
+        }
+    }
+    states
+}
+
+/// 去掉名称首尾空白并检查工作流结构
+pub fn normalize_workflow(body: &mut UpdateWorkflowSchema) -> Result<(), AppError> {
+    for state in &mut body.states {
+        state.name = state.name.trim().to_string();
+        if state.name.is_empty() || state.name.chars().count() > 30 {
+            return Err(AppError::BadRequest("状态名称需为 1-30 个字符".into()));
+        }
+        if state.wip_limit.is_some_and(|limit| !(1..=1000).contains(&limit)) {
+            return Err(AppError::BadRequest("WIP 上限需在 1-1000 之间".into()));
+        }
+    }
+
+    let mut names = HashSet::new();
+    let mut ids = HashSet::new();
+    for state in &body.states {
+        if !names.insert(state.name.as_str()) {
+            return Err(AppError::BadRequest(format!("状态名称重复: {}", state.name)));
+        }
+        if state.id.is_some_and(|id| !ids.insert(id)) {
+            return Err(AppError::BadRequest("同一状态出现了多次".into()));
+        }
+    }
+    if body.states[0].category.is_closed() {
+        return Err(AppError::BadRequest("第一个状态是新任务的初始状态，不能是已完成或已取消分类".into()));
+    }
+    if !body.states.iter().any(|s| s.category == StatusCategory::Completed) {
+        return Err(AppError::BadRequest("工作流至少需要一个已完成分类的状态".into()));
+    }
+
+    for transition in body.transitions.iter_mut().flatten() {
+        transition.from = transition.from.trim().to_string();
+        transition.to = transition.to.trim().to_string();
+        for name in [&transition.from, &transition.to] {
+            if !names.contains(name.as_str()) {
+                return Err(AppError::BadRequest(format!("流转规则中的状态不存在: {}", name)));
+            }
+        }
+        if transition.from == transition.to {
+            return Err(AppError::BadRequest("流转规则的起止状态不能相同".into()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{WorkflowStateInput, WorkflowTransition};
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn categories(list: &[&str]) -> Vec<StatusCategory> {
+        default_states(&names(list)).into_iter().map(|(_, c)| c).collect()
+    }
+
+    fn workflow(states: &[(&str, StatusCategory)], transitions: &[(&str, &str)]) -> UpdateWorkflowSchema {
+        UpdateWorkflowSchema {
+            states: states
+                .iter()
+                .map(|(name, category)| WorkflowStateInput {
+                    id: None,
+                    name: name.to_string(),
+                    category: *category,
+                    wip_limit: None,
+                })
+                .collect(),
+            transitions: Some(
+                transitions
+                    .iter()
+                    .map(|(from, to)| WorkflowTransition { from: from.to_string(), to: to.to_string() })
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn guesses_category_from_name() {
+        assert_eq!(guess_category("Backlog", true), StatusCategory::Backlog);
+        assert_eq!(guess_category("待办", false), StatusCategory::Unstarted);
+        assert_eq!(guess_category("in_progress", false), StatusCategory::Started);
+        assert_eq!(guess_category("Done", false), StatusCategory::Completed);
+        assert_eq!(guess_category("已取消", false), StatusCategory::Canceled);
+        // 第一个状态是初始状态，即使名字像完成也不会被关闭
+        assert_eq!(guess_category("done", true), StatusCategory::Unstarted);
+    }
+
+    #[test]
+    fn default_states_always_have_a_completed_state() {
+        use StatusCategory::*;
+        assert_eq!(categories(&["todo", "in_progress", "done", "canceled"]), [Unstarted, Started, Completed, Canceled]);
+        // 没有可识别的完成状态时，最后一个当作已完成
+        assert_eq!(categories(&["idea", "doing", "shipped"]), [Unstarted, Started, Completed]);
+        assert_eq!(categories(&["backlog", "review"]), [Backlog, Completed]);
+    }
+
+    #[test]
+    fn normalize_trims_and_accepts_valid_workflow() {
+        use StatusCategory::*;
+        let mut body = workflow(&[(" todo ", Unstarted), ("done ", Completed)], &[(" todo", "done ")]);
+        normalize_workflow(&mut body).unwrap();
+        assert_eq!(body.states[0].name, "todo");
+        assert_eq!(body.states[1].name, "done");
+        let transitions = body.transitions.unwrap();
+        assert_eq!((transitions[0].from.as_str(), transitions[0].to.as_str()), ("todo", "done"));
+    }
+
+    #[test]
+    fn normalize_rejects_invalid_structure() {
+        use StatusCategory::*;
+        let rejected = |states: &[(&str, StatusCategory)], transitions: &[(&str, &str)]| {
+            matches!(normalize_workflow(&mut workflow(states, transitions)), Err(AppError::BadRequest(_)))
+        };
+        // 名称为空或去掉空白后重复
+        assert!(rejected(&[("  ", Unstarted), ("done", Completed)], &[]));
+        assert!(rejected(&[("todo", Unstarted), ("todo ", Started), ("done", Completed)], &[]));
+        // 初始状态不能是关闭分类，且至少需要一个完成状态
+        assert!(rejected(&[("done", Completed), ("todo", Unstarted)], &[]));
+        assert!(rejected(&[("todo", Unstarted), ("doing", Started)], &[]));
+        // 流转规则引用不存在的状态或起止相同
+        assert!(rejected(&[("todo", Unstarted), ("done", Completed)], &[("todo", "gone")]));
+        assert!(rejected(&[("todo", Unstarted), ("done", Completed)], &[("done", "done")]));
+    }
+}